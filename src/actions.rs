@@ -3,13 +3,22 @@ use std::path::Path;
 use std::env;
 use std::fs::{create_dir_all, copy};
 use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use crate::archive::ArchiveOptions;
+use crate::journal::{Batch, Operation};
+use crate::protect::ProtectedPaths;
+use crate::trashcan::TrashEntry;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum FileActionError {
     PermissionDenied,
     FileNotFound,
     InsufficientSpace,
     FileInUse,
+    ContentMismatch,
+    /// The path is on the protected list, under the path given.
+    Protected(PathBuf),
     Other(String),
 }
 
@@ -41,12 +50,24 @@ impl std::fmt::Display for FileActionError {
             FileActionError::FileNotFound => write!(f, "File not found"),
             FileActionError::InsufficientSpace => write!(f, "Insufficient disk space"),
             FileActionError::FileInUse => write!(f, "File is currently in use"),
+            FileActionError::ContentMismatch => write!(f, "File contents do not match"),
+            FileActionError::Protected(root) => write!(f, "Protected path ({})", root.display()),
             FileActionError::Other(msg) => write!(f, "Error: {}", msg),
         }
     }
 }
 
+impl std::error::Error for FileActionError {}
+
 pub fn delete_file(path: &str) -> Result<(), FileActionError> {
+    delete_checked(path, &ProtectedPaths::load())
+}
+
+/// [`delete_file`] against a protected list the caller has already loaded,
+/// so a batch reads it once rather than once per file.
+pub(crate) fn delete_checked(path: &str, protected: &ProtectedPaths) -> Result<(), FileActionError> {
+    protected.check(Path::new(path))?;
+
     // Check if file exists first
     if !Path::new(path).exists() {
         return Err(FileActionError::FileNotFound);
@@ -63,107 +84,560 @@ pub fn delete_file(path: &str) -> Result<(), FileActionError> {
     Ok(())
 }
 
-pub fn archive_file(path: &str) -> Result<(), FileActionError> {
-    // Check if file exists
-    if !Path::new(path).exists() {
-        return Err(FileActionError::FileNotFound);
+/// TrashDoctor's own data directory, `~/.trashdoctor`.
+pub fn data_dir() -> PathBuf {
+    home_dir().join(".trashdoctor")
+}
+
+// $HOME, or the home directory from the password database when it is unset.
+// Never a shared temporary directory, where the data would not survive and
+// archiving would not free any space.
+pub(crate) fn home_dir() -> PathBuf {
+    match env::var_os("HOME") {
+        Some(home) if !home.is_empty() => PathBuf::from(home),
+        _ => passwd_home().unwrap_or_else(|| PathBuf::from(".")),
     }
+}
 
-    // Get home directory
-    let home = env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-    let archive_dir = format!("{}/.trashdoctor/archive", home);
+#[cfg(unix)]
+fn passwd_home() -> Option<PathBuf> {
+    use std::ffi::{CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
     
-    // Create archive directory if it doesn't exist
-    create_dir_all(&archive_dir)?;
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+    let mut result = std::ptr::null_mut();
+    let status = unsafe {
+        libc::getpwuid_r(libc::getuid(), &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result)
+    };
+    if status != 0 || result.is_null() || passwd.pw_dir.is_null() {
+        return None;
+    }
+    
+    let dir = unsafe { CStr::from_ptr(passwd.pw_dir) };
+    Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())))
+}
 
-    // Get filename and create unique archive path
-    let filename = Path::new(path)
-        .file_name()
-        .ok_or_else(|| FileActionError::Other("Invalid file path".to_string()))?
-        .to_str()
-        .ok_or_else(|| FileActionError::Other("Invalid filename encoding".to_string()))?;
+#[cfg(not(unix))]
+fn passwd_home() -> Option<PathBuf> {
+    None
+}
 
-    let mut archive_path = format!("{}/{}", archive_dir, filename);
-    let mut counter = 1;
-    
-    // Handle duplicate filenames by adding a counter
-    while Path::new(&archive_path).exists() {
-        let stem = Path::new(filename).file_stem().unwrap_or_default().to_str().unwrap_or("");
-        let ext = Path::new(filename).extension().unwrap_or_default().to_str().unwrap_or("");
-        if ext.is_empty() {
-            archive_path = format!("{}/{}_{}", archive_dir, stem, counter);
-        } else {
-            archive_path = format!("{}/{}_{}.{}", archive_dir, stem, counter, ext);
-        }
-        counter += 1;
-    }
+/// Archives a file into a bundle of its own and returns where it was stored.
+/// The archive manifest remembers the original location so it can be
+/// restored later.
+pub fn archive_file(path: &str) -> Result<String, FileActionError> {
+    crate::archive::store_files(&[path.to_string()], &ArchiveOptions::default())
+        .remove(0)
+        .1
+        .map(|entry| entry.location())
+}
 
-    // Copy file to archive
-    copy(path, &archive_path)?;
-    
-    // Delete original file
-    delete_file(path)?;
-    
-    Ok(())
+/// Moves a file to the trash and returns where it ended up, when known.
+pub fn move_to_trash(path: &str) -> Result<Option<String>, FileActionError> {
+    trash_checked(path, &ProtectedPaths::load())
 }
 
-pub fn move_to_trash(path: &str) -> Result<(), FileActionError> {
+fn trash_checked(path: &str, protected: &ProtectedPaths) -> Result<Option<String>, FileActionError> {
+    protected.check(Path::new(path))?;
+
     // Use system trash if available
     #[cfg(feature = "trash")]
     {
         use trash::delete;
         delete(path).map_err(|e| FileActionError::Other(e.to_string()))?;
-        Ok(())
+        Ok(None)
     }
     
     #[cfg(not(feature = "trash"))]
     {
-        // Fallback to manual trash implementation
-        let home = env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-        let trash_dir = format!("{}/.local/share/Trash/files", home);
-        let trash_info_dir = format!("{}/.local/share/Trash/info", home);
-        
-        create_dir_all(&trash_dir)?;
-        create_dir_all(&trash_info_dir)?;
-        
-        let filename = Path::new(path)
-            .file_name()
-            .ok_or_else(|| FileActionError::Other("Invalid file path".to_string()))?
-            .to_str()
-            .ok_or_else(|| FileActionError::Other("Invalid filename encoding".to_string()))?;
-        
-        let mut trash_path = format!("{}/{}", trash_dir, filename);
-        let mut counter = 1;
-        
-        // Handle duplicate filenames
-        while Path::new(&trash_path).exists() {
-            let stem = Path::new(filename).file_stem().unwrap_or_default().to_str().unwrap_or("");
-            let ext = Path::new(filename).extension().unwrap_or_default().to_str().unwrap_or("");
-            if ext.is_empty() {
-                trash_path = format!("{}/{}_{}", trash_dir, stem, counter);
-            } else {
-                trash_path = format!("{}/{}_{}.{}", trash_dir, stem, counter, ext);
+        crate::trashcan::trash(Path::new(path)).map(|trashed| Some(trashed.display().to_string()))
+    }
+}
+
+/// Result of a batch action, one entry per requested path.
+#[derive(Debug, Default)]
+pub struct BatchOutcome {
+    pub batch: u64,
+    pub results: Vec<(String, Result<(), FileActionError>)>,
+}
+
+impl BatchOutcome {
+    pub fn succeeded(&self) -> Vec<String> {
+        self.results.iter()
+            .filter(|(_, result)| result.is_ok())
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+    
+    pub fn failed_count(&self) -> usize {
+        self.results.iter().filter(|(_, result)| result.is_err()).count()
+    }
+}
+
+/// Deletes files, recording each one in the journal.
+pub fn delete_files(paths: &[String]) -> BatchOutcome {
+    run_batch(Operation::Delete, paths, |path, protected| delete_checked(path, protected).map(|_| None))
+}
+
+/// Archives files into one bundle, recording each one in the journal so it
+/// can be undone.
+pub fn archive_files(paths: &[String]) -> BatchOutcome {
+    archive_files_with(paths, &ArchiveOptions::default())
+}
+
+/// [`archive_files`] with a chosen compression level.
+pub fn archive_files_with(paths: &[String], options: &ArchiveOptions) -> BatchOutcome {
+    archive_checked(paths, options, &ProtectedPaths::load())
+}
+
+fn archive_checked(paths: &[String], options: &ArchiveOptions, protected: &ProtectedPaths) -> BatchOutcome {
+    let mut batch = match begin_batch(Operation::Archive, paths) {
+        Ok(batch) => batch,
+        Err(outcome) => return outcome,
+    };
+    
+    let mut outcome = BatchOutcome { batch: batch.id(), results: Vec::new() };
+    
+    let mut intended = Vec::new();
+    for path in paths {
+        match intend(&mut batch, path) {
+            Ok(_) => intended.push(path.clone()),
+            Err(error) => outcome.results.push((path.clone(), Err(error))),
+        }
+    }
+    
+    for (path, result) in crate::archive::store_files_checked(&intended, options, protected) {
+        let result = match result {
+            Ok(entry) => {
+                let _ = batch.record(&path, Some(entry.location()), entry.size, entry.mtime, entry.hash);
+                Ok(())
+            }
+            Err(error) => {
+                let _ = batch.fail(&path);
+                Err(error)
             }
-            counter += 1;
+        };
+        outcome.results.push((path, result));
+    }
+    
+    outcome
+}
+
+/// [`archive_files_with`] for files and whole directories. Every regular
+/// file under a directory goes into the bundle with a result of its own,
+/// and the directory is removed once they all have. A directory holding a
+/// protected path is refused as a whole.
+pub fn archive_paths_with(paths: &[String], options: &ArchiveOptions) -> BatchOutcome {
+    let protected = ProtectedPaths::load();
+    let mut refused = Vec::new();
+    let mut allowed = Vec::new();
+    for path in paths {
+        match protected.check(Path::new(path)) {
+            Err(error) if fs::symlink_metadata(path).is_ok_and(|m| m.is_dir()) => refused.push((path.clone(), Err(error))),
+            _ => allowed.push(path.clone()),
         }
+    }
+    
+    let mut outcome = match files_under(&allowed) {
+        files if files.is_empty() => BatchOutcome::default(),
+        files => archive_checked(&files, options, &protected),
+    };
+    for dir in allowed.iter().filter(|path| fs::symlink_metadata(path).is_ok_and(|m| m.is_dir())) {
+        let failed = outcome.results.iter().any(|(path, result)| result.is_err() && Path::new(path).starts_with(dir));
+        if !failed {
+            remove_empty_dirs(Path::new(dir));
+        }
+    }
+    outcome.results.extend(refused);
+    outcome
+}
+
+/// The regular files at or under `paths`, with directories expanded in
+/// walk order. Symlinks inside directories are not followed.
+pub fn files_under(paths: &[String]) -> Vec<String> {
+    paths.iter()
+        .flat_map(|path| {
+            walkdir::WalkDir::new(path)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|entry| entry.depth() == 0 || entry.file_type().is_file())
+                .map(|entry| entry.path().display().to_string())
+        })
+        .filter(|path| fs::metadata(path).is_ok_and(|m| m.is_file()))
+        .collect()
+}
+
+/// How many regular files [`files_under`] finds and their total size, each
+/// counted once. For a directory that is everything an action on it takes,
+/// including what a scan leaves out: hidden files, `.git`, `node_modules`
+/// and the like.
+pub fn disk_usage(paths: &[String]) -> (usize, u64) {
+    let files: std::collections::HashSet<String> = files_under(paths).into_iter().collect();
+    let size = files.iter().filter_map(|path| fs::metadata(path).ok()).map(|metadata| metadata.len()).sum();
+    (files.len(), size)
+}
+
+// Removes `dir` and the directories under it that are left empty, deepest
+// first; anything still holding a file stays
+fn remove_empty_dirs(dir: &Path) {
+    for entry in walkdir::WalkDir::new(dir).contents_first(true).into_iter().filter_map(Result::ok) {
+        if entry.file_type().is_dir() {
+            let _ = fs::remove_dir(entry.path());
+        }
+    }
+}
+
+/// Trashes files, recording each one in the journal so it can be undone.
+pub fn trash_files(paths: &[String]) -> BatchOutcome {
+    run_batch(Operation::Trash, paths, trash_checked)
+}
+
+/// Permanently deletes items from the trash, recording each one in the
+/// journal under its original path.
+pub fn purge_trash(entries: &[TrashEntry]) -> BatchOutcome {
+    let paths: Vec<String> = entries.iter().map(|entry| entry.original_path.display().to_string()).collect();
+    let mut batch = match begin_batch(Operation::Delete, &paths) {
+        Ok(batch) => batch,
+        Err(outcome) => return outcome,
+    };
+    
+    let mut outcome = BatchOutcome { batch: batch.id(), results: Vec::new() };
+    
+    for (entry, path) in entries.iter().zip(paths) {
+        if let Err(error) = batch.intend(&path, entry.size, 0) {
+            outcome.results.push((path, Err(error)));
+            continue;
+        }
+        let result = match crate::trashcan::purge(entry) {
+            Ok(()) => {
+                let _ = batch.record(&path, Some(entry.trashed_path.display().to_string()), entry.size, 0, None);
+                Ok(())
+            }
+            Err(error) => {
+                let _ = batch.fail(&path);
+                Err(error)
+            }
+        };
+        outcome.results.push((path, result));
+    }
+    
+    outcome
+}
+
+/// Replaces each `(original, duplicate)` pair's duplicate with a link,
+/// recording the original as the destination.
+pub fn link_duplicates(pairs: &[(String, String)], mode: LinkMode) -> BatchOutcome {
+    let originals: std::collections::HashMap<&str, &str> = pairs.iter()
+        .map(|(original, duplicate)| (duplicate.as_str(), original.as_str()))
+        .collect();
+    let duplicates: Vec<String> = pairs.iter().map(|(_, duplicate)| duplicate.clone()).collect();
+    
+    run_batch(Operation::Link, &duplicates, |path, protected| {
+        let original = originals[path];
+        link_checked(original, path, mode, protected).map(|_| Some(original.to_string()))
+    })
+}
+
+// The protected list is loaded once for the whole batch and handed to each
+// action.
+fn run_batch<F>(operation: Operation, paths: &[String], mut action: F) -> BatchOutcome
+where
+    F: FnMut(&str, &ProtectedPaths) -> Result<Option<String>, FileActionError>,
+{
+    let mut batch = match begin_batch(operation, paths) {
+        Ok(batch) => batch,
+        Err(outcome) => return outcome,
+    };
+    let protected = ProtectedPaths::load();
+    
+    let mut outcome = BatchOutcome { batch: batch.id(), results: Vec::new() };
+    
+    for path in paths {
+        let (size, mtime) = match intend(&mut batch, path) {
+            Ok(snapshot) => snapshot,
+            Err(error) => {
+                outcome.results.push((path.clone(), Err(error)));
+                continue;
+            }
+        };
         
-        // Move file to trash
-        fs::rename(path, &trash_path)?;
-        
-        // Create .trashinfo file
-        let trash_info_path = format!("{}/{}.trashinfo", trash_info_dir, 
-            Path::new(&trash_path).file_name().unwrap().to_str().unwrap());
-        
-        let deletion_date = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S").to_string();
-        let trash_info_content = format!(
-            "[Trash Info]\nPath={}\nDeletionDate={}\n",
-            path, deletion_date
-        );
+        let result = match action(path, &protected) {
+            Ok(destination) => {
+                let _ = batch.record(path, destination, size, mtime, None);
+                Ok(())
+            }
+            Err(error) => {
+                let _ = batch.fail(path);
+                Err(error)
+            }
+        };
+        outcome.results.push((path.clone(), result));
+    }
+    
+    outcome
+}
+
+// Writes the intent for `path` ahead of the action, with what the file
+// looked like before it is touched. Once the action has run its outcome is
+// confirmed on a best-effort basis: the file has been handled either way,
+// and an unconfirmed intent still tells undo about it.
+fn intend(batch: &mut Batch, path: &str) -> Result<(u64, u64), FileActionError> {
+    let (size, mtime) = fs::metadata(path)
+        .map(|metadata| {
+            let mtime = metadata.modified()
+                .ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0);
+            (metadata.len(), mtime)
+        })
+        .unwrap_or((0, 0));
+    batch.intend(path, size, mtime)?;
+    Ok((size, mtime))
+}
+
+// Without a journal nothing could be undone, so nothing is touched
+fn begin_batch(operation: Operation, paths: &[String]) -> Result<Batch, BatchOutcome> {
+    Batch::begin(operation).map_err(|error| BatchOutcome {
+        batch: 0,
+        results: paths.iter().map(|path| (path.clone(), Err(error.clone()))).collect(),
+    })
+}
+
+/// Moves a file or directory, falling back to copy and delete across
+/// filesystems.
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(error) if error.raw_os_error() == Some(18) => { // EXDEV
+            if let Err(error) = copy_tree(from, to) {
+                // Leave the source intact and drop the partial copy
+                let _ = remove_tree(to);
+                return Err(error);
+            }
+            remove_tree(from)
+        }
+        result => result,
+    }
+}
+
+// Copies a file, symlink or directory tree, keeping permissions and
+// modification times
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    
+    if metadata.file_type().is_symlink() {
+        #[cfg(unix)]
+        return std::os::unix::fs::symlink(fs::read_link(from)?, to);
+        #[cfg(not(unix))]
+        return Err(io::Error::new(ErrorKind::Unsupported, "cannot move symlinks across filesystems"));
+    }
+    
+    if metadata.is_dir() {
+        create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        copy(from, to)?;
+    }
+    
+    // Times are best effort: an unreadable copy cannot be opened to set them
+    if let (Ok(file), Ok(modified)) = (fs::File::open(to), metadata.modified()) {
+        let _ = file.set_modified(modified);
+    }
+    fs::set_permissions(to, metadata.permissions())
+}
+
+pub(crate) fn remove_tree(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// How [`replace_with_link`] shares data between duplicate files.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkMode {
+    /// Point both names at the same inode.
+    Hardlink,
+    /// Copy-on-write clone (FICLONE); each file keeps its own inode.
+    Reflink,
+    /// Reflink where the filesystem supports it, otherwise hardlink.
+    Auto,
+}
+
+/// Replaces `duplicate` with a link to the data of `original`.
+///
+/// Both files must be on the same filesystem and have identical contents,
+/// which is checked byte by byte first. A reflinked copy keeps the
+/// duplicate's permissions and timestamps. A hardlink shares the original's
+/// inode and metadata, so it is only made when mode and ownership already
+/// match. Returns the kind of link that was made.
+pub fn replace_with_link(original: &str, duplicate: &str, mode: LinkMode) -> Result<LinkMode, FileActionError> {
+    link_checked(original, duplicate, mode, &ProtectedPaths::load())
+}
+
+fn link_checked(original: &str, duplicate: &str, mode: LinkMode, protected: &ProtectedPaths) -> Result<LinkMode, FileActionError> {
+    if !Path::new(original).exists() || !Path::new(duplicate).exists() {
+        return Err(FileActionError::FileNotFound);
+    }
+    protected.check(Path::new(duplicate))?;
+    
+    let original_meta = fs::metadata(original)?;
+    let duplicate_meta = fs::metadata(duplicate)?;
+    if duplicate_meta.permissions().readonly() {
+        return Err(FileActionError::PermissionDenied);
+    }
+    
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if original_meta.dev() != duplicate_meta.dev() {
+            return Err(FileActionError::Other("Files are on different filesystems".to_string()));
+        }
+        if original_meta.ino() == duplicate_meta.ino() {
+            return Ok(LinkMode::Hardlink);
+        }
+    }
+    
+    if original_meta.len() != duplicate_meta.len() || !contents_equal(original, duplicate)? {
+        return Err(FileActionError::ContentMismatch);
+    }
+    
+    // Build the replacement next to the duplicate, then swap it in atomically
+    let duplicate_path = Path::new(duplicate);
+    let file_name = duplicate_path
+        .file_name()
+        .ok_or_else(|| FileActionError::Other("Invalid file path".to_string()))?
+        .to_string_lossy();
+    let temp_path = duplicate_path.with_file_name(format!(".{}.trashdoctor-link", file_name));
+    
+    let made = match mode {
+        LinkMode::Hardlink => hardlink_into(original, &original_meta, &duplicate_meta, &temp_path)?,
+        LinkMode::Reflink => reflink_into(original, &duplicate_meta, &temp_path)?,
+        LinkMode::Auto => match reflink_into(original, &duplicate_meta, &temp_path) {
+            Ok(made) => made,
+            Err(_) => hardlink_into(original, &original_meta, &duplicate_meta, &temp_path)?,
+        },
+    };
+    
+    if let Err(error) = fs::rename(&temp_path, duplicate) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(error.into());
+    }
+    
+    Ok(made)
+}
+
+/// Checks that the copy in each `(kept, copy)` pair still matches the kept
+/// file byte for byte. Duplicate groups are found by hashing, which only
+/// picks out candidates, so this runs before a copy is deleted, archived or
+/// trashed in favour of the one kept. A copy that differs fails with
+/// [`FileActionError::ContentMismatch`].
+pub fn verify_duplicates(pairs: &[(String, String)]) -> Vec<(String, Result<(), FileActionError>)> {
+    pairs.iter()
+        .map(|(kept, copy)| {
+            let same = fs::metadata(kept)
+                .and_then(|kept_meta| Ok(kept_meta.len() == fs::metadata(copy)?.len()))
+                .map_err(FileActionError::from)
+                .and_then(|same_size| Ok(same_size && contents_equal(kept, copy)?));
+            let result = match same {
+                Ok(true) => Ok(()),
+                Ok(false) => Err(FileActionError::ContentMismatch),
+                Err(error) => Err(error),
+            };
+            (copy.clone(), result)
+        })
+        .collect()
+}
+
+fn contents_equal(a: &str, b: &str) -> Result<bool, FileActionError> {
+    use std::io::Read;
+    
+    let mut file_a = fs::File::open(a)?;
+    let mut file_b = fs::File::open(b)?;
+    let mut buffer_a = vec![0u8; 64 * 1024];
+    let mut buffer_b = vec![0u8; 64 * 1024];
+    
+    loop {
+        let read = file_a.read(&mut buffer_a)?;
+        if read == 0 {
+            // Both files have the same length, so b is exhausted too
+            return Ok(true);
+        }
+        file_b.read_exact(&mut buffer_b[..read])?;
+        if buffer_a[..read] != buffer_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+fn hardlink_into(
+    original: &str,
+    original_meta: &fs::Metadata,
+    duplicate_meta: &fs::Metadata,
+    temp_path: &Path,
+) -> Result<LinkMode, FileActionError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if original_meta.mode() != duplicate_meta.mode()
+            || original_meta.uid() != duplicate_meta.uid()
+            || original_meta.gid() != duplicate_meta.gid()
+        {
+            return Err(FileActionError::Other(
+                "Permissions differ; a hardlink would change them".to_string(),
+            ));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (original_meta, duplicate_meta);
+    
+    fs::hard_link(original, temp_path)?;
+    Ok(LinkMode::Hardlink)
+}
+
+#[cfg(target_os = "linux")]
+fn reflink_into(original: &str, duplicate_meta: &fs::Metadata, temp_path: &Path) -> Result<LinkMode, FileActionError> {
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::AsRawFd;
+    
+    // _IOW(0x94, 9, int) from linux/fs.h
+    const FICLONE: u64 = 0x4004_9409;
+    
+    let source = fs::File::open(original)?;
+    let target = fs::OpenOptions::new().write(true).create_new(true).open(temp_path)?;
+    
+    let result = (|| {
+        if unsafe { libc::ioctl(target.as_raw_fd(), FICLONE as _, source.as_raw_fd()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
         
-        fs::write(&trash_info_path, trash_info_content)?;
+        target.set_permissions(duplicate_meta.permissions())?;
+        if unsafe { libc::fchown(target.as_raw_fd(), duplicate_meta.uid(), duplicate_meta.gid()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
         
-        Ok(())
+        let times = fs::FileTimes::new()
+            .set_accessed(duplicate_meta.accessed()?)
+            .set_modified(duplicate_meta.modified()?);
+        target.set_times(times)
+    })();
+    
+    if let Err(error) = result {
+        let _ = fs::remove_file(temp_path);
+        return Err(error.into());
     }
+    
+    Ok(LinkMode::Reflink)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink_into(_original: &str, _duplicate_meta: &fs::Metadata, _temp_path: &Path) -> Result<LinkMode, FileActionError> {
+    Err(FileActionError::Other("Reflinks are not supported on this platform".to_string()))
 }
 
 pub fn get_file_size(path: &str) -> Result<u64, FileActionError> {
@@ -171,6 +645,26 @@ pub fn get_file_size(path: &str) -> Result<u64, FileActionError> {
     Ok(metadata.len())
 }
 
+/// Bytes available to unprivileged users on the filesystem holding `path`.
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Result<u64, FileActionError> {
+    use std::os::unix::ffi::OsStrExt;
+    
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| FileActionError::Other("Invalid path".to_string()))?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    
+    Ok(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Result<u64, FileActionError> {
+    Ok(u64::MAX)
+}
+
 pub fn is_file_writable(path: &str) -> Result<bool, FileActionError> {
     let metadata = fs::metadata(path)?;
     Ok(!metadata.permissions().readonly())
@@ -214,6 +708,35 @@ mod tests {
         assert_eq!(format_file_size(1073741824), "1.00 GB");
     }
     
+    #[test]
+    fn test_replace_with_link() {
+        let dir = env::temp_dir().join(format!("trashdoctor-link-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let original = dir.join("original.txt");
+        let duplicate = dir.join("duplicate.txt");
+        let different = dir.join("different.txt");
+        fs::write(&original, "same contents").unwrap();
+        fs::write(&duplicate, "same contents").unwrap();
+        fs::write(&different, "other content").unwrap();
+        let (original, duplicate, different) = (
+            original.to_str().unwrap(),
+            duplicate.to_str().unwrap(),
+            different.to_str().unwrap(),
+        );
+        
+        let pairs = [(original.to_string(), duplicate.to_string()), (original.to_string(), different.to_string())];
+        let verified: Vec<bool> = verify_duplicates(&pairs).into_iter().map(|(_, result)| result.is_ok()).collect();
+        let mismatch = replace_with_link(original, different, LinkMode::Hardlink);
+        let linked = replace_with_link(original, duplicate, LinkMode::Hardlink);
+        let contents = fs::read_to_string(duplicate).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        
+        assert_eq!(verified, [true, false]);
+        assert!(matches!(mismatch, Err(FileActionError::ContentMismatch)));
+        assert_eq!(linked.unwrap(), LinkMode::Hardlink);
+        assert_eq!(contents, "same contents");
+    }
+    
     #[test]
     fn test_get_file_type() {
         assert_eq!(get_file_type("test.txt"), "txt");
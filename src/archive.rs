@@ -0,0 +1,447 @@
+use crate::actions::{available_space, data_dir, delete_checked, move_path, FileActionError};
+use crate::rules::RuleConfig;
+use crate::bundle::{self, BundleWriter};
+use crate::protect::ProtectedPaths;
+use crate::scanner::{hash_file, hash_reader};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// A file held in the archive, and where it came from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub id: String,
+    /// The bundle holding the file, or for older entries the loose copy.
+    pub stored_path: String,
+    /// Name of the file inside its bundle.
+    #[serde(default)]
+    pub member: Option<String>,
+    pub original_path: String,
+    pub size: u64,
+    pub mtime: u64,
+    pub mode: u32,
+    pub archived_at: String,
+    pub hash: Option<String>,
+}
+
+// The manifest is append-only: entries are stored, and later restored
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum ManifestRecord {
+    Stored(ArchiveEntry),
+    Restored { id: String },
+}
+
+pub fn archive_dir() -> PathBuf {
+    data_dir().join("archive")
+}
+
+pub fn manifest_path() -> PathBuf {
+    archive_dir().join("manifest.jsonl")
+}
+
+/// Entries currently in the archive, oldest first.
+pub fn list_entries() -> Result<Vec<ArchiveEntry>, FileActionError> {
+    let file = match File::open(manifest_path()) {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut order = Vec::new();
+    let mut entries: HashMap<String, ArchiveEntry> = HashMap::new();
+
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        match serde_json::from_str(&line) {
+            Ok(ManifestRecord::Stored(entry)) => {
+                order.push(entry.id.clone());
+                entries.insert(entry.id.clone(), entry);
+            }
+            Ok(ManifestRecord::Restored { id }) => {
+                entries.remove(&id);
+            }
+            Err(_) => continue,
+        }
+    }
+
+    Ok(order.into_iter().filter_map(|id| entries.remove(&id)).collect())
+}
+
+/// How and where files are packed into archive bundles.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchiveOptions {
+    /// Gzip level from 0 (store only) to 9 (smallest).
+    pub compression_level: u32,
+    /// Existing directory to write bundles to, such as an external disk or
+    /// a NAS mount. `None` uses [`bundles_dir`].
+    pub destination: Option<PathBuf>,
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        Self { compression_level: 6, destination: None }
+    }
+}
+
+impl ArchiveOptions {
+    /// These options with the rule's archive destination filled in, unless
+    /// a destination was already chosen for this run.
+    pub fn with_rule(&self, rule: &RuleConfig) -> Self {
+        Self {
+            destination: self.destination.clone()
+                .or_else(|| rule.archive_destination.as_ref().map(PathBuf::from)),
+            ..self.clone()
+        }
+    }
+}
+
+impl ArchiveEntry {
+    /// Where the entry lives: `bundle#member`, or the object path for files
+    /// archived loose by older versions.
+    pub fn location(&self) -> String {
+        match &self.member {
+            Some(member) => format!("{}#{}", self.stored_path, member),
+            None => self.stored_path.clone(),
+        }
+    }
+}
+
+pub fn bundles_dir() -> PathBuf {
+    archive_dir().join("bundles")
+}
+
+/// Packs files into a new dated tar.gz bundle and removes the originals.
+///
+/// Nothing is copied unless the destination has room for all of the files
+/// uncompressed. The bundle is read back and every member hashed before anything is
+/// deleted; files whose copy does not verify are left in place. A JSON
+/// index listing the bundle's entries is written next to it. Results are in
+/// the same order as `paths`.
+pub fn store_files(paths: &[String], options: &ArchiveOptions) -> Vec<(String, Result<ArchiveEntry, FileActionError>)> {
+    store_files_checked(paths, options, &ProtectedPaths::load())
+}
+
+/// [`store_files`] against a protected list the caller has already loaded.
+pub(crate) fn store_files_checked(
+    paths: &[String],
+    options: &ArchiveOptions,
+    protected: &ProtectedPaths,
+) -> Vec<(String, Result<ArchiveEntry, FileActionError>)> {
+    let fail_all = |error: FileActionError| {
+        paths.iter().map(|path| (path.clone(), Err(error.clone()))).collect()
+    };
+
+    let (bundle_id, bundle_path) = match bundle_dir(options)
+        .and_then(|dir| check_free_space(&dir, paths).map(|_| dir))
+        .and_then(|dir| new_bundle(&dir))
+    {
+        Ok(bundle) => bundle,
+        Err(error) => return fail_all(error),
+    };
+    let mut writer = match BundleWriter::create(&bundle_path, options.compression_level) {
+        Ok(writer) => writer,
+        Err(error) => return fail_all(error.into()),
+    };
+
+    let mut written: Vec<Result<ArchiveEntry, FileActionError>> = paths.iter()
+        .enumerate()
+        .map(|(index, path)| add_to_bundle(&mut writer, path, &format!("{}-{}", bundle_id, index), index, &bundle_path, protected))
+        .collect();
+
+    // Read the finished bundle back; only members that hash the same as
+    // their source may replace it
+    let mut member_hashes = HashMap::new();
+    let verified = writer.finish().and_then(|_| {
+        bundle::for_each_member(&bundle_path, |name, _, contents| {
+            member_hashes.insert(name.to_string(), hash_reader(contents)?);
+            Ok(true)
+        })
+    });
+    if let Err(error) = verified {
+        let _ = fs::remove_file(&bundle_path);
+        return fail_all(FileActionError::Other(format!("Bundle verification failed: {}", error)));
+    }
+
+    let mut stored = Vec::new();
+    for (path, result) in paths.iter().zip(&mut written) {
+        let Ok(entry) = result else { continue };
+        let member_hash = entry.member.as_ref().and_then(|member| member_hashes.get(member));
+        if member_hash != entry.hash.as_ref() {
+            *result = Err(FileActionError::ContentMismatch);
+            continue;
+        }
+
+        if let Err(error) = append_record(&ManifestRecord::Stored(entry.clone())) {
+            *result = Err(error);
+            continue;
+        }
+        if let Err(error) = delete_checked(path, protected) {
+            // The original stays put, so the archived copy is withdrawn
+            let _ = append_record(&ManifestRecord::Restored { id: entry.id.clone() });
+            *result = Err(error);
+            continue;
+        }
+        stored.push(entry.clone());
+    }
+
+    if stored.is_empty() {
+        let _ = fs::remove_file(&bundle_path);
+    } else if let Ok(index) = serde_json::to_string_pretty(&stored) {
+        let _ = fs::write(index_path(&bundle_path), index);
+    }
+
+    paths.iter().cloned().zip(written).collect()
+}
+
+// A chosen destination must already exist, so an unmounted drive is never
+// quietly replaced by a new directory on the local disk
+fn bundle_dir(options: &ArchiveOptions) -> Result<PathBuf, FileActionError> {
+    match &options.destination {
+        Some(dir) if dir.is_dir() => Ok(dir.clone()),
+        Some(dir) => Err(FileActionError::Other(format!("Archive destination {} is not a directory", dir.display()))),
+        None => {
+            let dir = bundles_dir();
+            fs::create_dir_all(&dir)?;
+            Ok(dir)
+        }
+    }
+}
+
+// Sized for the uncompressed data and tar headers, plus a little for gzip
+// framing, so the copy cannot run out of room halfway
+pub(crate) fn check_free_space(dir: &Path, paths: &[String]) -> Result<(), FileActionError> {
+    let data: u64 = paths.iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|metadata| metadata.len().div_ceil(512) * 512 + 512)
+        .sum();
+    let needed = data + data / 100 + 64 * 1024;
+
+    if available_space(dir)? < needed {
+        return Err(FileActionError::InsufficientSpace);
+    }
+    Ok(())
+}
+
+// Picks an unused dated bundle name, e.g. 2024-05-01-1714567890123-0.tar.gz
+fn new_bundle(dir: &Path) -> Result<(String, PathBuf), FileActionError> {
+    let now = chrono::Local::now();
+    let mut counter = 0;
+    loop {
+        let id = format!("{}-{}", now.timestamp_millis(), counter);
+        let path = dir.join(format!("{}-{}.tar.gz", now.format("%Y-%m-%d"), id));
+        if !path.exists() {
+            return Ok((id, path));
+        }
+        counter += 1;
+    }
+}
+
+fn index_path(bundle_path: &Path) -> PathBuf {
+    bundle_path.with_extension("").with_extension("index.json")
+}
+
+fn add_to_bundle(
+    writer: &mut BundleWriter,
+    path: &str,
+    id: &str,
+    index: usize,
+    bundle_path: &Path,
+    protected: &ProtectedPaths,
+) -> Result<ArchiveEntry, FileActionError> {
+    protected.check(Path::new(path))?;
+    let source = Path::new(path);
+    let metadata = fs::metadata(source)?;
+    if !metadata.is_file() {
+        return Err(FileActionError::Other("Only regular files can be archived".to_string()));
+    }
+
+    let original_path = fs::canonicalize(source)?.display().to_string();
+    let filename = source
+        .file_name()
+        .ok_or_else(|| FileActionError::Other("Invalid file path".to_string()))?
+        .to_string_lossy()
+        .to_string();
+    let member = member_name(index, &filename);
+
+    let hash = hash_file(path)?;
+    let mtime = unix_secs(metadata.modified().ok());
+    let mode = file_mode(&metadata);
+    writer.append(&member, metadata.len(), mode, mtime, &mut File::open(source)?)?;
+
+    Ok(ArchiveEntry {
+        id: id.to_string(),
+        stored_path: bundle_path.display().to_string(),
+        member: Some(member),
+        original_path,
+        size: metadata.len(),
+        mtime,
+        mode,
+        archived_at: chrono::Local::now().to_rfc3339(),
+        hash: Some(hash),
+    })
+}
+
+// The index keeps names unique; the file name is only there to help anyone
+// opening the bundle with tar. Member names are limited to 100 bytes.
+fn member_name(index: usize, filename: &str) -> String {
+    let mut name = format!("{:06}-", index);
+    for c in filename.chars() {
+        if name.len() + c.len_utf8() > 100 {
+            break;
+        }
+        name.push(c);
+    }
+    name
+}
+
+/// Brings an archived file back out of the archive.
+///
+/// With no `target_dir` the file returns to its original path; otherwise it
+/// is placed in `target_dir` under its original name. Existing files are
+/// never overwritten. Returns the restored path.
+pub fn restore(id: &str, target_dir: Option<&Path>) -> Result<String, FileActionError> {
+    let entry = list_entries()?
+        .into_iter()
+        .find(|entry| entry.id == id)
+        .ok_or(FileActionError::FileNotFound)?;
+
+    let original = Path::new(&entry.original_path);
+    let target = match target_dir {
+        Some(dir) => dir.join(original.file_name().unwrap_or_default()),
+        None => original.to_path_buf(),
+    };
+
+    if target.exists() {
+        return Err(FileActionError::Other(format!("{} already exists", target.display())));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match &entry.member {
+        Some(member) => extract_member(&entry, member, &target)?,
+        None => move_path(Path::new(&entry.stored_path), &target)?,
+    }
+    append_record(&ManifestRecord::Restored { id: entry.id.clone() })?;
+
+    if entry.member.is_some() {
+        remove_bundle_if_empty(&entry.stored_path);
+    }
+
+    Ok(target.display().to_string())
+}
+
+/// Finds the entry stored at `location`, as returned by [`ArchiveEntry::location`].
+pub fn find_by_location(location: &str) -> Result<Option<ArchiveEntry>, FileActionError> {
+    Ok(list_entries()?.into_iter().find(|entry| entry.location() == location))
+}
+
+// Writes one bundle member to `target`, checking it against the recorded hash
+fn extract_member(entry: &ArchiveEntry, member: &str, target: &Path) -> Result<(), FileActionError> {
+    let mut output = File::options().write(true).create_new(true).open(target)?;
+
+    let mut found = false;
+    let result = bundle::for_each_member(Path::new(&entry.stored_path), |name, _, contents| {
+        if name != member {
+            return Ok(true);
+        }
+        io::copy(contents, &mut output)?;
+        found = true;
+        Ok(false)
+    })
+    .map_err(FileActionError::from)
+    .and_then(|_| {
+        if !found {
+            return Err(FileActionError::FileNotFound);
+        }
+        output.sync_all()?;
+        if entry.hash.is_some() && hash_file(&target.display().to_string()).ok() != entry.hash {
+            return Err(FileActionError::ContentMismatch);
+        }
+        restore_metadata(&output, entry)
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(target);
+    }
+    result
+}
+
+fn restore_metadata(file: &File, entry: &ArchiveEntry) -> Result<(), FileActionError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(entry.mode))?;
+    }
+    file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(entry.mtime))?;
+    Ok(())
+}
+
+// Once every member has been restored the bundle and its index can go
+fn remove_bundle_if_empty(bundle_path: &str) {
+    if let Ok(entries) = list_entries() {
+        if !entries.iter().any(|entry| entry.stored_path == bundle_path) {
+            let _ = fs::remove_file(bundle_path);
+            let _ = fs::remove_file(index_path(Path::new(bundle_path)));
+        }
+    }
+}
+
+fn append_record(record: &ManifestRecord) -> Result<(), FileActionError> {
+    fs::create_dir_all(archive_dir())?;
+    let mut file = OpenOptions::new().create(true).append(true).open(manifest_path())?;
+    let mut line = serde_json::to_string(record)
+        .map_err(|e| FileActionError::Other(e.to_string()))?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+fn unix_secs(time: Option<std::time::SystemTime>) -> u64 {
+    time.and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> u32 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_record_format() {
+        let record = ManifestRecord::Restored { id: "42-0".to_string() };
+        let line = serde_json::to_string(&record).unwrap();
+        assert_eq!(line, r#"{"event":"restored","id":"42-0"}"#);
+        assert!(matches!(serde_json::from_str(&line).unwrap(), ManifestRecord::Restored { id } if id == "42-0"));
+    }
+
+    #[test]
+    fn test_missing_destination_touches_nothing() {
+        let source = std::env::temp_dir().join(format!("trashdoctor-keep-{}.txt", std::process::id()));
+        fs::write(&source, "keep me").unwrap();
+        let options = ArchiveOptions {
+            destination: Some(PathBuf::from("/nonexistent/trashdoctor-mount")),
+            ..Default::default()
+        };
+
+        let results = store_files(&[source.display().to_string()], &options);
+        let still_there = source.exists();
+        fs::remove_file(&source).unwrap();
+
+        assert!(results[0].1.is_err());
+        assert!(still_there);
+    }
+}
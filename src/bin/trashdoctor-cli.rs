@@ -0,0 +1,5 @@
+// Headless entry point for machines without a display
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(trashdoctor::cli::run(&args));
+}
@@ -0,0 +1,207 @@
+//! Minimal tar.gz reading and writing for archive bundles.
+//!
+//! Only plain files are stored, under short member names chosen by the
+//! archive, so the ustar header fields are all that is needed. Sizes past
+//! the octal limit use the GNU base-256 encoding.
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const BLOCK: usize = 512;
+
+pub(crate) struct BundleWriter {
+    encoder: GzEncoder<BufWriter<File>>,
+}
+
+impl BundleWriter {
+    pub fn create(path: &Path, level: u32) -> io::Result<Self> {
+        let file = File::options().write(true).create_new(true).open(path)?;
+        Ok(Self {
+            encoder: GzEncoder::new(BufWriter::new(file), Compression::new(level.min(9))),
+        })
+    }
+
+    /// Appends `size` bytes from `reader` as member `name`.
+    ///
+    /// If the reader fails or runs out early (a file that shrank mid-copy),
+    /// the member is zero-filled to its declared size so the rest of the
+    /// bundle stays readable, and an error is returned.
+    pub fn append(&mut self, name: &str, size: u64, mode: u32, mtime: u64, reader: &mut dyn Read) -> io::Result<()> {
+        self.encoder.write_all(&header(name, size, mode, mtime)?)?;
+
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut remaining = size;
+        let mut read_error = None;
+        while remaining > 0 {
+            let want = remaining.min(buffer.len() as u64) as usize;
+            match reader.read(&mut buffer[..want]) {
+                Ok(0) => break,
+                Ok(read) => {
+                    self.encoder.write_all(&buffer[..read])?;
+                    remaining -= read as u64;
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    read_error = Some(error);
+                    break;
+                }
+            }
+        }
+
+        let padding = (BLOCK as u64 - (size % BLOCK as u64)) % BLOCK as u64;
+        io::copy(&mut io::repeat(0).take(remaining + padding), &mut self.encoder)?;
+
+        match read_error {
+            Some(error) => Err(error),
+            None if remaining > 0 => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file changed while archiving")),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the end-of-archive marker and flushes everything to disk.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.encoder.write_all(&[0u8; BLOCK * 2])?;
+        let writer = self.encoder.finish()?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()
+    }
+}
+
+/// Calls `visit` with each member's name, size and contents, stopping when
+/// it returns `false`. Unread contents are skipped automatically.
+pub(crate) fn for_each_member<F>(path: &Path, mut visit: F) -> io::Result<()>
+where
+    F: FnMut(&str, u64, &mut dyn Read) -> io::Result<bool>,
+{
+    let mut decoder = GzDecoder::new(BufReader::new(File::open(path)?));
+    let mut block = [0u8; BLOCK];
+
+    loop {
+        decoder.read_exact(&mut block)?;
+        if block.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+
+        let name = parse_name(&block);
+        let size = parse_size(&block[124..136])?;
+
+        let mut contents = (&mut decoder).take(size);
+        let keep_going = visit(&name, size, &mut contents)?;
+        io::copy(&mut contents, &mut io::sink())?;
+        if !keep_going {
+            return Ok(());
+        }
+
+        let padding = (BLOCK as u64 - (size % BLOCK as u64)) % BLOCK as u64;
+        decoder.read_exact(&mut block[..padding as usize])?;
+    }
+}
+
+fn header(name: &str, size: u64, mode: u32, mtime: u64) -> io::Result<[u8; BLOCK]> {
+    if name.len() > 100 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "member name too long"));
+    }
+
+    let mut block = [0u8; BLOCK];
+    block[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut block[100..108], (mode & 0o7777) as u64);
+    write_octal(&mut block[108..116], 0);
+    write_octal(&mut block[116..124], 0);
+    write_size(&mut block[124..136], size);
+    write_octal(&mut block[136..148], mtime);
+    block[156] = b'0';
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with its own field filled with spaces
+    block[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = block.iter().map(|&b| b as u32).sum();
+    write_octal(&mut block[148..155], checksum as u64);
+    block[155] = b' ';
+
+    Ok(block)
+}
+
+// Zero-padded octal followed by a NUL, filling the field
+fn write_octal(field: &mut [u8], value: u64) {
+    let width = field.len() - 1;
+    let digits = format!("{:0width$o}", value, width = width);
+    field[..width].copy_from_slice(&digits.as_bytes()[digits.len() - width..]);
+    field[width] = 0;
+}
+
+fn write_size(field: &mut [u8], size: u64) {
+    if size < 0o77777777777 {
+        write_octal(field, size);
+    } else {
+        // GNU base-256: high bit set, big-endian value in the remaining bytes
+        field.fill(0);
+        field[0] = 0x80;
+        let len = field.len();
+        field[len - 8..].copy_from_slice(&size.to_be_bytes());
+    }
+}
+
+fn parse_name(block: &[u8; BLOCK]) -> String {
+    let end = block[..100].iter().position(|&b| b == 0).unwrap_or(100);
+    String::from_utf8_lossy(&block[..end]).to_string()
+}
+
+fn parse_size(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[field.len() - 8..].iter().fold(0u64, |acc, &b| (acc << 8) | b as u64));
+    }
+
+    let digits: String = field.iter()
+        .take_while(|&&b| b != 0 && b != b' ')
+        .map(|&b| b as char)
+        .collect();
+    u64::from_str_radix(digits.trim(), 8)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "corrupt tar header"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let path = std::env::temp_dir().join(format!("trashdoctor-bundle-{}.tar.gz", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let big = vec![7u8; 1500];
+        let mut writer = BundleWriter::create(&path, 6).unwrap();
+        writer.append("000000", 5, 0o644, 0, &mut &b"hello"[..]).unwrap();
+        writer.append("000001", big.len() as u64, 0o600, 0, &mut &big[..]).unwrap();
+        assert!(writer.append("000002", 10, 0o644, 0, &mut &b"short"[..]).is_err());
+        writer.finish().unwrap();
+
+        let mut members = Vec::new();
+        for_each_member(&path, |name, size, contents| {
+            let mut data = Vec::new();
+            contents.read_to_end(&mut data)?;
+            members.push((name.to_string(), size, data));
+            Ok(true)
+        })
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(members.len(), 3);
+        assert_eq!(members[0], ("000000".to_string(), 5, b"hello".to_vec()));
+        assert_eq!(members[1].2, big);
+        assert_eq!(members[2].2, b"short\0\0\0\0\0".to_vec());
+    }
+
+    #[test]
+    fn test_size_encoding() {
+        let mut field = [0u8; 12];
+        write_size(&mut field, 12345);
+        assert_eq!(parse_size(&field).unwrap(), 12345);
+        write_size(&mut field, 20 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size(&field).unwrap(), 20 * 1024 * 1024 * 1024);
+    }
+}
@@ -0,0 +1,922 @@
+use crate::scanner::{scan_folder_with_options, file_info, skip_reason, ScanOptions, get_file_type_statistics, get_largest_files, calculate_space_savings};
+use crate::rules::{self, apply_rules, RuleConfig, SmartRule};
+use crate::actions::{FileActionError, delete_files, archive_files_with, trash_files, purge_trash, format_file_size};
+use crate::text::capitalize;
+use crate::journal::{undo_last_batch, last_batch, skip_last_batch};
+use crate::archive::{self, ArchiveOptions};
+use crate::trashcan::{self, TrashEntry};
+use crate::plan::{self, Plan};
+use crate::mounts;
+use crate::protect::{self, ProtectedPaths};
+use crate::tree::DirTree;
+
+const USAGE: &str = "\
+Usage: trashdoctor <command> <folder> [options]
+
+Commands:
+  scan <folder>       Scan a folder and print a summary, with its largest
+                      folders
+  list <folder>       List files matching the rule
+  clean <folder>      Permanently delete files matching the rule
+  archive <folder>    Pack files matching the rule into a compressed archive bundle
+  trash <folder>      Move files matching the rule to the trash
+  explain <file>      Show every check the rule makes on a file and whether it
+                      passed (exit status 0 if the file matches)
+  undo                Restore the most recent archive or trash batch
+                      (--skip gives up on files still in the way and moves
+                      on to the batch before)
+  archived            List files held in the archive
+  restore <id>...     Restore archived files (--to <dir> for another location)
+  trashed             List files in the trash, including other drives' trash
+  untrash <path>...   Restore trashed files by original or trashed path (--to <dir>)
+  purge               Permanently delete trash older than --older-than <DAYS>
+                      or beyond --max-size <MB> (requires --yes)
+  apply <plan>        Carry out a plan saved with --plan-out (requires --yes)
+  rules               List saved and predefined rules
+  rules show <name>   Print a rule as TOML
+  rules import <file> Copy a .toml or .json rule file into the rules folder
+  rules export <name> <file>
+                      Write a rule to a .toml or .json file to share it
+  rules delete <name> Remove a saved rule
+  protected           List the paths TrashDoctor never touches
+  help                Show this message
+
+Scan options:
+  --include-hidden        Include hidden files in the scan
+  --include-system        Also scan protected paths such as /usr and ~/.ssh;
+                          their files are listed but never touched
+  --max-depth <N>         Limit recursion depth
+  --follow-symlinks       Follow symbolic links
+  --threads <N>           Read directories on N threads (default: one per CPU)
+  --ext <a,b,...>         Only scan files with these extensions
+  --skip <PATTERN>        Skip paths matching pattern (repeatable)
+
+Rule options:
+  --rule <NAME>           Start from a saved or predefined rule; the options
+                          below adjust it (defaults: --age 30 --min-size 100)
+  --rule-file <FILE>      Start from a .toml or .json rule file
+  --save-rule <NAME>      Save the resulting rule for later --rule use
+  --age <DAYS>            Minimum age in days (default 30)
+  --age-basis <BASIS>     Timestamp the age is counted from: access (default),
+                          modify, change, birth, newest or oldest
+  --min-size <MB>         Minimum file size in MB (default 100)
+  --max-size <MB>         Maximum file size in MB
+  --type <T,...>          Only match these file types (e.g. Video,Archive)
+  --not-type <T,...>      Never match these file types
+  --hidden                Allow hidden files to match
+  --readonly              Allow read-only files to match
+  --executable            Allow executable files to match
+  --match <PATTERN>       Only match paths matching pattern (repeatable)
+  --exclude <PATTERN>     Never match paths matching pattern (repeatable)
+  --where <EXPR>          Also require a rule expression, e.g.
+                          '(in:~/Downloads older:30d or name:*.iso larger:1G)
+                          and not in:~/keep'
+
+Action options:
+  -y, --yes               Required to actually clean, archive or trash files
+  --dry-run               Show what clean, archive or trash would do, and why
+                          any file would fail, without touching anything
+  --plan-out <FILE>       Save the dry-run plan as JSON for 'apply' (implies --dry-run)
+  --level <0-9>           Compression level for archive bundles (default 6)
+  --archive-to <DIR>      Write archive bundles to DIR, e.g. another disk
+";
+
+#[derive(Debug, Clone, PartialEq)]
+enum CliCommand {
+    Scan,
+    List,
+    Clean,
+    Archive,
+    Trash,
+    Explain,
+}
+
+#[derive(Debug, Clone)]
+struct CliArgs {
+    command: CliCommand,
+    folder: String,
+    scan: ScanOptions,
+    rule: RuleConfig,
+    /// The rule the options started from, if any.
+    base_rule: Option<SmartRule>,
+    save_rule: Option<String>,
+    archive: ArchiveOptions,
+    yes: bool,
+    dry_run: bool,
+    plan_out: Option<std::path::PathBuf>,
+}
+
+/// Runs the command-line interface and returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    if args.is_empty() || matches!(args[0].as_str(), "help" | "-h" | "--help") {
+        print!("{}", USAGE);
+        return 0;
+    }
+
+    match args[0].as_str() {
+        "undo" => return run_undo(&args[1..]),
+        "archived" => return run_archived(),
+        "restore" => return run_restore(&args[1..]),
+        "trashed" => return run_trashed(),
+        "untrash" => return run_untrash(&args[1..]),
+        "purge" => return run_purge(&args[1..]),
+        "apply" => return run_apply(&args[1..]),
+        "rules" => return run_rules(&args[1..]),
+        "protected" => return run_protected(),
+        _ => {}
+    }
+
+    let cli = match parse_args(args) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("trashdoctor: {}", err);
+            eprintln!("Try 'trashdoctor help' for more information.");
+            return 2;
+        }
+    };
+
+    if let Some(name) = &cli.save_rule {
+        let description = cli.base_rule.as_ref()
+            .map(|base| base.description.clone())
+            .unwrap_or_else(|| "Saved from the command line".to_string());
+        match rules::save_rule(&SmartRule::new(name, &description, cli.rule.clone())) {
+            Ok(path) => println!("Saved rule '{}' to {}", name, path.display()),
+            Err(err) => {
+                eprintln!("trashdoctor: cannot save rule: {}", err);
+                return 1;
+            }
+        }
+    }
+
+    if cli.command == CliCommand::Explain {
+        return run_explain(&cli);
+    }
+
+    if !std::path::Path::new(&cli.folder).is_dir() {
+        eprintln!("trashdoctor: '{}' is not a directory", cli.folder);
+        return 2;
+    }
+
+    let files = scan_folder_with_options(&cli.folder, &cli.scan);
+    if cli.command != CliCommand::Scan {
+        warn_about_atime(&files, &cli.rule);
+    }
+
+    match cli.command {
+        CliCommand::Scan => {
+            print_summary(&cli.folder, &files, cli.rule.age_basis);
+            0
+        }
+        CliCommand::List => {
+            let matched = apply_rules(&files, &cli.rule);
+            for file in &matched {
+                println!("{:>10}  {}  {}", format_file_size(file.size), file.last_accessed, file.path);
+            }
+            println!(
+                "{} files, {} total",
+                matched.len(),
+                format_file_size(matched.iter().map(|f| f.size).sum())
+            );
+            0
+        }
+        CliCommand::Explain => unreachable!("handled before scanning"),
+        CliCommand::Clean | CliCommand::Archive | CliCommand::Trash => {
+            let matched = apply_rules(&files, &cli.rule);
+            if matched.is_empty() {
+                println!("No files matched.");
+                return 0;
+            }
+
+            let paths: Vec<String> = matched.iter().map(|f| f.path.clone()).collect();
+            let archive = cli.archive.with_rule(&cli.rule);
+
+            if cli.dry_run || cli.plan_out.is_some() {
+                let plan = match cli.command {
+                    CliCommand::Clean => plan::plan_delete(&paths),
+                    CliCommand::Archive => plan::plan_archive(&paths, &archive),
+                    _ => plan::plan_trash(&paths),
+                };
+                print_plan(&plan);
+                if let Some(out) = &cli.plan_out {
+                    if let Err(err) = plan.save(out) {
+                        eprintln!("trashdoctor: cannot write plan to {}: {}", out.display(), err);
+                        return 1;
+                    }
+                    println!("Plan saved to {}; run 'trashdoctor apply {} --yes' to carry it out.", out.display(), out.display());
+                }
+                return 0;
+            }
+
+            if !cli.yes {
+                eprintln!(
+                    "trashdoctor: {} files ({}) matched; re-run with --yes to proceed",
+                    matched.len(),
+                    format_file_size(matched.iter().map(|f| f.size).sum())
+                );
+                return 1;
+            }
+
+            let (verb, outcome) = match cli.command {
+                CliCommand::Clean => ("deleted", delete_files(&paths)),
+                CliCommand::Archive => ("archived", archive_files_with(&paths, &archive)),
+                _ => ("trashed", trash_files(&paths)),
+            };
+            for (path, result) in &outcome.results {
+                if let Err(err) = result {
+                    eprintln!("{}: {}", path, err);
+                }
+            }
+
+            let failed = outcome.failed_count();
+            println!("{} {} files, {} failed.", capitalize(verb), outcome.results.len() - failed, failed);
+            if failed == 0 { 0 } else { 1 }
+        }
+    }
+}
+
+fn run_undo(args: &[String]) -> i32 {
+    match args {
+        [] => {}
+        [flag] if flag == "--skip" => return run_skip_undo(),
+        [other, ..] => {
+            eprintln!("trashdoctor: unexpected argument '{}'", other);
+            return 2;
+        }
+    }
+
+    let pending = match last_batch() {
+        Ok(pending) => pending,
+        Err(err) => {
+            eprintln!("trashdoctor: cannot read journal: {}", err);
+            return 1;
+        }
+    };
+    if pending.is_empty() {
+        println!("Nothing to undo.");
+        return 0;
+    }
+
+    match undo_last_batch() {
+        Ok(report) => {
+            for path in &report.restored {
+                println!("restored  {}", path);
+            }
+            for (path, reason) in &report.skipped {
+                eprintln!("skipped   {}: {}", path, reason);
+            }
+            println!("Restored {} files, skipped {}.", report.restored.len(), report.skipped.len());
+            if report.blocked > 0 {
+                println!("Run undo again once they are free, or undo --skip to move on to the batch before.");
+            }
+            if report.skipped.is_empty() { 0 } else { 1 }
+        }
+        Err(err) => {
+            eprintln!("trashdoctor: undo failed: {}", err);
+            1
+        }
+    }
+}
+
+fn run_skip_undo() -> i32 {
+    match skip_last_batch() {
+        Ok(entries) if entries.is_empty() => {
+            println!("Nothing to undo.");
+            0
+        }
+        Ok(entries) => {
+            for entry in &entries {
+                println!("gave up   {}", entry.original_path);
+            }
+            println!("Gave up on {} files; undo now moves on to the batch before.", entries.len());
+            0
+        }
+        Err(err) => {
+            eprintln!("trashdoctor: cannot write journal: {}", err);
+            1
+        }
+    }
+}
+
+fn run_archived() -> i32 {
+    match archive::list_entries() {
+        Ok(entries) => {
+            for entry in &entries {
+                println!(
+                    "{:<16}  {:>10}  {}  {}",
+                    entry.id,
+                    format_file_size(entry.size),
+                    &entry.archived_at[..entry.archived_at.len().min(19)],
+                    entry.original_path
+                );
+            }
+            println!("{} archived files", entries.len());
+            0
+        }
+        Err(err) => {
+            eprintln!("trashdoctor: cannot read archive manifest: {}", err);
+            1
+        }
+    }
+}
+
+fn run_restore(args: &[String]) -> i32 {
+    let (ids, target) = match split_target(args) {
+        Ok(split) => split,
+        Err(err) => {
+            eprintln!("trashdoctor: {}", err);
+            return 2;
+        }
+    };
+
+    if ids.is_empty() {
+        eprintln!("trashdoctor: restore needs at least one archive id (see 'trashdoctor archived')");
+        return 2;
+    }
+
+    let mut failed = 0;
+    for id in &ids {
+        match archive::restore(id, target.as_deref()) {
+            Ok(path) => println!("restored  {}", path),
+            Err(err) => {
+                eprintln!("{}: {}", id, err);
+                failed += 1;
+            }
+        }
+    }
+    if failed == 0 { 0 } else { 1 }
+}
+
+fn run_trashed() -> i32 {
+    match trashcan::list_entries() {
+        Ok(entries) => {
+            for entry in &entries {
+                println!(
+                    "{:<16}  {:>10}  {}",
+                    format_deleted_at(entry),
+                    format_file_size(entry.size),
+                    entry.original_path.display()
+                );
+            }
+            println!(
+                "{} items in the trash, {} total",
+                entries.len(),
+                format_file_size(entries.iter().map(|e| e.size).sum())
+            );
+            0
+        }
+        Err(err) => {
+            eprintln!("trashdoctor: cannot read the trash: {}", err);
+            1
+        }
+    }
+}
+
+fn run_untrash(args: &[String]) -> i32 {
+    let (paths, target) = match split_target(args) {
+        Ok(split) => split,
+        Err(err) => {
+            eprintln!("trashdoctor: {}", err);
+            return 2;
+        }
+    };
+    if paths.is_empty() {
+        eprintln!("trashdoctor: untrash needs at least one path (see 'trashdoctor trashed')");
+        return 2;
+    }
+
+    let entries = match trashcan::list_entries() {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("trashdoctor: cannot read the trash: {}", err);
+            return 1;
+        }
+    };
+
+    let mut failed = 0;
+    for path in &paths {
+        let wanted = std::path::Path::new(path);
+        // Entries are oldest first, so the latest deletion of a path wins
+        let found = entries.iter()
+            .rev()
+            .find(|entry| entry.original_path == wanted || entry.trashed_path == wanted);
+        let result = match found {
+            Some(entry) => trashcan::restore(entry, target.as_deref()),
+            None => Err(crate::actions::FileActionError::FileNotFound),
+        };
+        match result {
+            Ok(restored) => println!("restored  {}", restored.display()),
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                failed += 1;
+            }
+        }
+    }
+    if failed == 0 { 0 } else { 1 }
+}
+
+fn run_purge(args: &[String]) -> i32 {
+    let (older_than, max_size, yes) = match parse_purge_args(args) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("trashdoctor: {}", err);
+            return 2;
+        }
+    };
+
+    let entries = match trashcan::list_entries() {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("trashdoctor: cannot read the trash: {}", err);
+            return 1;
+        }
+    };
+
+    let now = chrono::Local::now().naive_local();
+    let mut doomed: Vec<TrashEntry> = match older_than {
+        Some(days) => trashcan::older_than(&entries, days, now).into_iter().cloned().collect(),
+        None => Vec::new(),
+    };
+    // The size cap applies to whatever the age limit leaves behind
+    if let Some(max_bytes) = max_size {
+        let remaining: Vec<TrashEntry> = entries.iter()
+            .filter(|entry| !doomed.iter().any(|d| d.trashed_path == entry.trashed_path))
+            .cloned()
+            .collect();
+        doomed.extend(trashcan::over_size_cap(&remaining, max_bytes).into_iter().cloned());
+    }
+
+    if doomed.is_empty() {
+        println!("Nothing to purge.");
+        return 0;
+    }
+
+    let total = format_file_size(doomed.iter().map(|e| e.size).sum());
+    if !yes {
+        for entry in &doomed {
+            println!("{:<16}  {:>10}  {}", format_deleted_at(entry), format_file_size(entry.size), entry.original_path.display());
+        }
+        eprintln!("trashdoctor: {} items ({}) would be purged; re-run with --yes to proceed", doomed.len(), total);
+        return 1;
+    }
+
+    let outcome = purge_trash(&doomed);
+    for (path, result) in &outcome.results {
+        if let Err(err) = result {
+            eprintln!("{}: {}", path, err);
+        }
+    }
+    let failed = outcome.failed_count();
+    println!("Purged {} items, {} failed.", outcome.results.len() - failed, failed);
+    if failed == 0 { 0 } else { 1 }
+}
+
+fn run_explain(cli: &CliArgs) -> i32 {
+    let path = std::path::Path::new(&cli.folder);
+    let file = match file_info(path) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("trashdoctor: {}: {}", cli.folder, err);
+            return 2;
+        }
+    };
+
+    warn_about_atime(std::slice::from_ref(&file), &cli.rule);
+    let explanation = rules::explain(&file, &cli.rule);
+    let name_width = explanation.checks.iter().map(|check| 2 * check.depth + check.name.len()).max().unwrap_or(0);
+    let observed_width = explanation.checks.iter().map(|check| check.observed.len()).max().unwrap_or(0);
+    for check in &explanation.checks {
+        println!(
+            "{}  {:<name_width$}  {:<observed_width$}  {}",
+            if check.passed { "pass" } else { "FAIL" },
+            format!("{}{}", "  ".repeat(check.depth), check.name),
+            check.observed,
+            check.expected
+        );
+    }
+
+    let rule_name = cli.base_rule.as_ref()
+        .map(|rule| format!("rule '{}'", rule.name))
+        .unwrap_or_else(|| "the rule".to_string());
+    let verdict = if explanation.matched { "matches" } else { "does not match" };
+    println!("{} {} {}.", cli.folder, verdict, rule_name);
+    if let Some(reason) = skip_reason(path, &cli.scan) {
+        println!("A scan would not list it anyway: {}.", reason);
+    }
+    if let Err(FileActionError::Protected(protected)) = protect::check(&cli.folder) {
+        println!("It would never be deleted, archived or trashed: {} is protected.", protected.display());
+    }
+    if explanation.matched { 0 } else { 1 }
+}
+
+fn warn_about_atime(files: &[crate::scanner::FileInfo], rule: &RuleConfig) {
+    if let Some(warning) = mounts::atime_warning(files, rule) {
+        eprintln!("trashdoctor: warning: {} (--age-basis modify).", warning);
+    }
+}
+
+fn run_protected() -> i32 {
+    for path in ProtectedPaths::load().paths() {
+        println!("{}", path.display());
+    }
+    println!("\nAdd paths to {}, one per line.", protect::protected_file().display());
+    0
+}
+
+fn run_apply(args: &[String]) -> i32 {
+    let mut file = None;
+    let mut yes = false;
+    for arg in args {
+        match arg.as_str() {
+            "-y" | "--yes" => yes = true,
+            other if file.is_none() => file = Some(other.to_string()),
+            other => {
+                eprintln!("trashdoctor: unexpected argument '{}'", other);
+                return 2;
+            }
+        }
+    }
+    let Some(file) = file else {
+        eprintln!("trashdoctor: apply needs a plan file (see --plan-out)");
+        return 2;
+    };
+
+    let plan = match Plan::load(std::path::Path::new(&file)) {
+        Ok(plan) => plan,
+        Err(err) => {
+            eprintln!("trashdoctor: cannot read plan {}: {}", file, err);
+            return 1;
+        }
+    };
+
+    print_plan(&plan);
+    if !yes {
+        eprintln!("trashdoctor: re-run with --yes to carry out this plan");
+        return 1;
+    }
+
+    let outcome = plan::execute(&plan);
+    for (path, result) in &outcome.results {
+        if let Err(err) = result {
+            eprintln!("{}: {}", path, err);
+        }
+    }
+    let failed = outcome.failed_count();
+    println!("Applied {} steps, {} failed.", outcome.results.len() - failed, failed);
+    if failed == 0 { 0 } else { 1 }
+}
+
+fn print_plan(plan: &Plan) {
+    let verb = format!("{:?}", plan.operation).to_lowercase();
+    for step in &plan.steps {
+        let destination = step.destination.as_deref()
+            .map(|dest| format!(" -> {}", dest))
+            .unwrap_or_default();
+        println!("{:<8} {:>10}  {}{}", verb, format_file_size(step.bytes), step.path, destination);
+        for problem in &step.problems {
+            println!("{:<8} {:>10}  ! {}", "", "", problem);
+        }
+    }
+
+    let ready = plan.ready().count();
+    println!(
+        "{} of {} files ready, {} would be freed.",
+        ready,
+        plan.steps.len(),
+        format_file_size(plan.bytes_freed())
+    );
+}
+
+fn run_rules(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        [] => {
+            let (saved, errors) = rules::load_saved_rules();
+            for rule in &saved {
+                println!("{:<10} {:<28} {}", "saved", rule.name, rule.description);
+            }
+            for rule in rules::get_predefined_rules() {
+                println!("{:<10} {:<28} {}", "built-in", rule.name, rule.description);
+            }
+            for err in &errors {
+                eprintln!("trashdoctor: skipped unreadable rule {}", err);
+            }
+            println!("Saved rules are kept in {}", rules::rules_dir().display());
+            Ok(())
+        }
+        ["show", name] => named_rule(name).and_then(|rule| {
+            toml::to_string_pretty(&rule)
+                .map(|text| print!("{}", text))
+                .map_err(|e| e.to_string())
+        }),
+        ["import", file] => rules::load_rule_file(std::path::Path::new(file))
+            .and_then(|rule| rules::save_rule(&rule).map(|path| (rule, path)))
+            .map(|(rule, path)| println!("Imported rule '{}' to {}", rule.name, path.display()))
+            .map_err(|e| e.to_string()),
+        ["export", name, file] => named_rule(name).and_then(|rule| {
+            rules::save_rule_file(&rule, std::path::Path::new(file))
+                .map(|_| println!("Wrote rule '{}' to {}", rule.name, file))
+                .map_err(|e| e.to_string())
+        }),
+        ["delete", name] => rules::delete_saved_rule(name)
+            .map(|_| println!("Deleted rule '{}'", name))
+            .map_err(|e| e.to_string()),
+        _ => {
+            eprintln!("trashdoctor: usage: rules [show <name> | import <file> | export <name> <file> | delete <name>]");
+            return 2;
+        }
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("trashdoctor: {}", err);
+            1
+        }
+    }
+}
+
+fn named_rule(name: &str) -> Result<SmartRule, String> {
+    rules::find_rule(name).ok_or_else(|| no_such_rule(name))
+}
+
+fn no_such_rule(name: &str) -> String {
+    format!("no rule named '{}' (see 'trashdoctor rules')", name)
+}
+
+// Finds the --rule or --rule-file the other rule options adjust, wherever
+// it appears on the command line. `find_rule` looks up a --rule name.
+fn base_rule(args: &[String], find_rule: impl Fn(&str) -> Option<SmartRule>) -> Result<Option<SmartRule>, String> {
+    let mut base = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let loaded = match arg.as_str() {
+            "--rule" => {
+                let name = iter.next().ok_or("--rule requires a value")?;
+                find_rule(name).ok_or_else(|| no_such_rule(name))?
+            }
+            "--rule-file" => {
+                let file = iter.next().ok_or("--rule-file requires a value")?;
+                rules::load_rule_file(std::path::Path::new(file)).map_err(|e| e.to_string())?
+            }
+            _ => continue,
+        };
+        if base.is_some() {
+            return Err("use only one of --rule and --rule-file".to_string());
+        }
+        base = Some(loaded);
+    }
+    Ok(base)
+}
+
+// Returns --older-than days, --max-size in bytes and whether --yes was given
+fn parse_purge_args(args: &[String]) -> Result<(Option<u64>, Option<u64>, bool), String> {
+    let mut older_than = None;
+    let mut max_size = None;
+    let mut yes = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| -> Result<String, String> {
+            iter.next().cloned().ok_or_else(|| format!("{} requires a value", name))
+        };
+
+        match arg.as_str() {
+            "--older-than" => older_than = Some(parse_number(arg, &value(arg)?)?),
+            "--max-size" => {
+                let mb = parse_number(arg, &value(arg)?)?;
+                max_size = Some(rules::mb_to_bytes(mb).ok_or_else(|| format!("--max-size {} is too large", mb))?);
+            }
+            "-y" | "--yes" => yes = true,
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+
+    if older_than.is_none() && max_size.is_none() {
+        return Err("purge needs --older-than <DAYS> and/or --max-size <MB>".to_string());
+    }
+    Ok((older_than, max_size, yes))
+}
+
+fn format_deleted_at(entry: &TrashEntry) -> String {
+    entry.deleted_at
+        .map(|deleted| deleted.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// Splits "--to <dir>" off the positional arguments of restore and untrash
+fn split_target(args: &[String]) -> Result<(Vec<String>, Option<std::path::PathBuf>), String> {
+    let mut positional = Vec::new();
+    let mut target = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--to" => target = Some(iter.next().ok_or("--to requires a value")?.into()),
+            other => positional.push(other.to_string()),
+        }
+    }
+    Ok((positional, target))
+}
+
+fn parse_args(args: &[String]) -> Result<CliArgs, String> {
+    parse_args_with(args, rules::find_rule)
+}
+
+// parse_args with the --rule lookup passed in, so tests need not read the
+// user's saved rules
+fn parse_args_with(args: &[String], find_rule: impl Fn(&str) -> Option<SmartRule>) -> Result<CliArgs, String> {
+    let command = match args[0].as_str() {
+        "scan" => CliCommand::Scan,
+        "list" => CliCommand::List,
+        "clean" => CliCommand::Clean,
+        "archive" => CliCommand::Archive,
+        "trash" => CliCommand::Trash,
+        "explain" => CliCommand::Explain,
+        other => return Err(format!("unknown command '{}'", other)),
+    };
+
+    // Same defaults as the GUI filters, unless a rule was named
+    let base_rule = base_rule(&args[1..], find_rule)?;
+    let mut rule = match &base_rule {
+        Some(base) => base.config.clone(),
+        None => RuleConfig {
+            max_age_days: 30,
+            min_size_mb: 100,
+            ..Default::default()
+        },
+    };
+    let mut save_rule = None;
+    let mut scan = ScanOptions::default();
+    let mut archive = ArchiveOptions::default();
+    let mut folder = None;
+    let mut yes = false;
+    let mut dry_run = false;
+    let mut plan_out = None;
+
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| -> Result<String, String> {
+            iter.next().cloned().ok_or_else(|| format!("{} requires a value", name))
+        };
+
+        match arg.as_str() {
+            "--include-hidden" => scan.include_hidden = true,
+            "--include-system" => scan.include_system = true,
+            "--max-depth" => scan.max_depth = Some(parse_number(arg, &value(arg)?)? as usize),
+            "--follow-symlinks" => scan.follow_symlinks = true,
+            "--threads" => scan.threads = parse_number(arg, &value(arg)?)? as usize,
+            "--ext" => scan.file_extensions = Some(split_list(&value(arg)?)),
+            "--skip" => scan.exclude_patterns.push(value(arg)?),
+            "--age" => rule.max_age_days = parse_number(arg, &value(arg)?)?,
+            "--age-basis" => rule.age_basis = value(arg)?.parse()?,
+            "--min-size" => rule.min_size_mb = parse_number(arg, &value(arg)?)?,
+            "--max-size" => rule.max_size_mb = Some(parse_number(arg, &value(arg)?)?),
+            "--type" => rule.file_types = Some(split_list(&value(arg)?)),
+            "--not-type" => rule.exclude_file_types = Some(split_list(&value(arg)?)),
+            "--hidden" => rule.include_hidden = true,
+            "--readonly" => rule.include_readonly = true,
+            "--executable" => rule.include_executable = true,
+            "--match" => rule.custom_patterns.push(value(arg)?),
+            "--exclude" => rule.exclude_patterns.push(value(arg)?),
+            "--where" => rule.expression = Some(value(arg)?),
+            "--rule" | "--rule-file" => {
+                value(arg)?;
+            }
+            "--save-rule" => save_rule = Some(value(arg)?),
+            "-y" | "--yes" => yes = true,
+            "--dry-run" => dry_run = true,
+            "--plan-out" => plan_out = Some(value(arg)?.into()),
+            "--level" => {
+                archive.compression_level = match parse_number(arg, &value(arg)?)? {
+                    level @ 0..=9 => level as u32,
+                    _ => return Err("--level must be between 0 and 9".to_string()),
+                };
+            }
+            "--archive-to" => archive.destination = Some(value(arg)?.into()),
+            flag if flag.starts_with('-') => return Err(format!("unknown option '{}'", flag)),
+            path => {
+                if folder.is_some() {
+                    return Err(format!("unexpected argument '{}'", path));
+                }
+                folder = Some(path.to_string());
+            }
+        }
+    }
+
+    let folder = folder.ok_or_else(|| match command {
+        CliCommand::Explain => "missing file argument".to_string(),
+        _ => "missing folder argument".to_string(),
+    })?;
+    if let Some(expression) = &rule.expression {
+        crate::expr::parse(expression).map_err(|e| format!("invalid rule expression: {}", e))?;
+    }
+    rule.check_limits()?;
+
+    Ok(CliArgs { command, folder, scan, rule, base_rule, save_rule, archive, yes, dry_run, plan_out })
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<u64, String> {
+    value.parse().map_err(|_| format!("{} expects a number, got '{}'", flag, value))
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn print_summary(folder: &str, files: &[crate::scanner::FileInfo], basis: rules::AgeBasis) {
+    let total_size: u64 = files.iter().map(|f| f.size).sum();
+    println!("Total: {} files ({})", files.len(), format_file_size(total_size));
+
+    let mut stats: Vec<_> = get_file_type_statistics(files).into_iter().collect();
+    stats.sort_by_key(|(_, (_, size))| std::cmp::Reverse(*size));
+    println!("\nBy type:");
+    for (file_type, (count, size)) in stats {
+        println!("  {:<16} {:>8} files  {:>10}", file_type, count, format_file_size(size));
+    }
+
+    println!("\nLargest files:");
+    for file in get_largest_files(files, 10) {
+        println!("  {:>10}  {}", format_file_size(file.size), file.path);
+    }
+
+    println!("\nLargest folders:");
+    let date = |secs: u64| {
+        chrono::DateTime::from_timestamp(secs as i64, 0)
+            .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    };
+    for dir in DirTree::build(folder, files, basis).largest(10) {
+        println!(
+            "  {:>10}  {:>8} files  {} to {}  {}",
+            format_file_size(dir.size),
+            dir.file_count,
+            date(dir.oldest_secs),
+            date(dir.newest_secs),
+            dir.path
+        );
+    }
+
+    let (savings, duplicates) = calculate_space_savings(files);
+    println!("\nDuplicate copies: {} files ({} reclaimable)", duplicates, format_file_size(savings));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    // Looks --rule up among the built-in rules only
+    fn parse(list: &[&str]) -> Result<CliArgs, String> {
+        parse_args_with(&args(list), |name| {
+            rules::get_predefined_rules().into_iter().find(|rule| rule.name.eq_ignore_ascii_case(name))
+        })
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let cli = parse(&["list", "/tmp", "--age", "90", "--type", "Video,Audio", "--skip", "*.iso"]).unwrap();
+        assert_eq!(cli.command, CliCommand::List);
+        assert_eq!(cli.folder, "/tmp");
+        assert_eq!(cli.rule.max_age_days, 90);
+        assert_eq!(cli.rule.min_size_mb, 100);
+        assert_eq!(cli.rule.file_types, Some(vec!["Video".to_string(), "Audio".to_string()]));
+        assert!(cli.scan.exclude_patterns.contains(&"*.iso".to_string()));
+        assert!(!cli.yes);
+        assert!(!cli.dry_run);
+
+        let cli = parse(&["trash", "/tmp", "--plan-out", "plan.json"]).unwrap();
+        assert_eq!(cli.plan_out, Some("plan.json".into()));
+
+        // Options adjust the named rule whichever side of it they appear on
+        let cli = parse(&["list", "/tmp", "--age", "7", "--rule", "huge files"]).unwrap();
+        assert_eq!(cli.rule.min_size_mb, 500);
+        assert_eq!(cli.rule.max_age_days, 7);
+
+        let cli = parse(&["list", "/tmp", "--age-basis", "Modify"]).unwrap();
+        assert_eq!(cli.rule.age_basis, rules::AgeBasis::Modify);
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert!(parse(&["frobnicate", "/tmp"]).is_err());
+        assert!(parse(&["list"]).is_err());
+        assert!(parse(&["list", "/tmp", "--age"]).is_err());
+        assert!(parse(&["list", "/tmp", "--age", "old"]).is_err());
+        assert!(parse(&["archive", "/tmp", "--level", "12"]).is_err());
+        assert!(parse(&["archive", "/tmp", "--level", "4294967297"]).is_err());
+        assert!(parse(&["list", "/tmp", "--where", "(name:*.iso"]).is_err());
+        assert!(parse(&["list", "/tmp", "--age-basis", "atime"]).is_err());
+        assert!(parse(&["list", "/tmp", "--rule", "no such rule"]).is_err());
+        assert!(parse(&["clean", "/tmp", "--age", "999999999999999", "--yes"]).is_err());
+        assert!(parse(&["clean", "/tmp", "--max-size", "99999999999999999"]).is_err());
+        assert!(parse_purge_args(&args(&["--yes"])).is_err());
+        assert_eq!(parse_purge_args(&args(&["--older-than", "30", "-y"])), Ok((Some(30), None, true)));
+        assert_eq!(parse_purge_args(&args(&["--max-size", "2"])), Ok((None, Some(2 * 1024 * 1024), false)));
+        assert!(parse_purge_args(&args(&["--max-size", "99999999999999999"])).is_err());
+    }
+}
@@ -0,0 +1,502 @@
+//! Rule expressions: predicates on a file combined with `and`, `or`, `not`
+//! and parentheses, for rules the fixed [`RuleConfig`](crate::RuleConfig)
+//! fields cannot express.
+//!
+//! ```text
+//! (in:~/Downloads older:30d or name:*.iso larger:1G) and not in:~/keep
+//! ```
+//!
+//! Predicates written next to each other are joined with `and`. `not`
+//! binds tightest, then `and`, then `or`. Values containing spaces or
+//! parentheses can be quoted: `in:"~/My Files"`. Ages are counted from the
+//! rule's [`AgeBasis`], the last access time unless the rule says otherwise.
+//!
+//! | Predicate        | Matches files                                        |
+//! |------------------|------------------------------------------------------|
+//! | `in:DIR`         | anywhere under DIR (`~` is the home folder)          |
+//! | `path:PATTERN`   | whose full path matches, as in `--match`             |
+//! | `name:PATTERN`   | whose file name matches                              |
+//! | `ext:a,b`        | with one of these extensions                         |
+//! | `type:T`         | of this file type, e.g. `type:video`                 |
+//! | `older:AGE`      | at least AGE old (`12h`, `30d`, `2w`, `1y`)          |
+//! | `newer:AGE`      | less than AGE old                                    |
+//! | `larger:SIZE`    | bigger than SIZE (`500K`, `10M`, `1G`; bare numbers are MB) |
+//! | `smaller:SIZE`   | smaller than SIZE                                    |
+//! | `hidden`, `readonly`, `executable` | with that attribute                |
+
+use crate::actions::{format_file_size, home_dir};
+use crate::rules::{format_age, pattern_matches, AgeBasis, Check};
+use crate::scanner::FileInfo;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Predicate(Predicate),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+    In(PathBuf),
+    Path(String),
+    Name(String),
+    Ext(Vec<String>),
+    Type(String),
+    /// Age in seconds.
+    Older(u64),
+    Newer(u64),
+    /// Size in bytes.
+    Larger(u64),
+    Smaller(u64),
+    Hidden,
+    Readonly,
+    Executable,
+}
+
+impl Expr {
+    pub fn matches(&self, file: &FileInfo, now_secs: u64, basis: AgeBasis) -> bool {
+        match self {
+            Expr::And(all) => all.iter().all(|e| e.matches(file, now_secs, basis)),
+            Expr::Or(any) => any.iter().any(|e| e.matches(file, now_secs, basis)),
+            Expr::Not(inner) => !inner.matches(file, now_secs, basis),
+            Expr::Predicate(predicate) => predicate.matches(file, now_secs, basis),
+        }
+    }
+
+    /// Every age the expression compares against, in seconds.
+    pub fn ages(&self) -> Vec<u64> {
+        match self {
+            Expr::And(all) | Expr::Or(all) => all.iter().flat_map(Expr::ages).collect(),
+            Expr::Not(inner) => inner.ages(),
+            Expr::Predicate(Predicate::Older(secs) | Predicate::Newer(secs)) => vec![*secs],
+            Expr::Predicate(_) => Vec::new(),
+        }
+    }
+
+    /// [`matches`](Self::matches), recording a [`Check`] for this node and
+    /// everything under it. Every branch is evaluated so the whole tree
+    /// shows up.
+    pub fn trace(&self, file: &FileInfo, now_secs: u64, basis: AgeBasis, depth: usize, checks: &mut Vec<Check>) -> bool {
+        let at = checks.len();
+        let (name, passed) = match self {
+            Expr::And(all) => {
+                let results: Vec<bool> = all.iter().map(|e| e.trace(file, now_secs, basis, depth + 1, checks)).collect();
+                ("all of", results.iter().all(|&r| r))
+            }
+            Expr::Or(any) => {
+                let results: Vec<bool> = any.iter().map(|e| e.trace(file, now_secs, basis, depth + 1, checks)).collect();
+                ("any of", results.iter().any(|&r| r))
+            }
+            Expr::Not(inner) => ("not", !inner.trace(file, now_secs, basis, depth + 1, checks)),
+            Expr::Predicate(predicate) => {
+                let passed = predicate.matches(file, now_secs, basis);
+                checks.push(Check {
+                    name: predicate.to_string(),
+                    observed: predicate.observed(file, now_secs, basis),
+                    expected: predicate.expected(),
+                    passed,
+                    depth,
+                });
+                return passed;
+            }
+        };
+
+        checks.insert(at, Check {
+            name: name.to_string(),
+            observed: if passed { "matched" } else { "did not match" }.to_string(),
+            expected: String::new(),
+            passed,
+            depth,
+        });
+        passed
+    }
+}
+
+impl Predicate {
+    pub fn matches(&self, file: &FileInfo, now_secs: u64, basis: AgeBasis) -> bool {
+        let path = Path::new(&file.path);
+        let age = basis.age_secs(file, now_secs);
+
+        match self {
+            Predicate::In(dir) => under(path, dir),
+            Predicate::Path(pattern) => pattern_matches(&file.path, pattern),
+            Predicate::Name(pattern) => path.file_name()
+                .is_some_and(|name| pattern_matches(&name.to_string_lossy(), pattern)),
+            Predicate::Ext(extensions) => path.extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .is_some_and(|ext| extensions.contains(&ext)),
+            Predicate::Type(file_type) => file.file_type.to_lowercase().contains(file_type),
+            Predicate::Older(secs) => age >= *secs,
+            Predicate::Newer(secs) => age < *secs,
+            Predicate::Larger(bytes) => file.size > *bytes,
+            Predicate::Smaller(bytes) => file.size < *bytes,
+            Predicate::Hidden => file.is_hidden,
+            Predicate::Readonly => file.is_readonly,
+            Predicate::Executable => file.is_executable,
+        }
+    }
+
+    fn observed(&self, file: &FileInfo, now_secs: u64, basis: AgeBasis) -> String {
+        let path = Path::new(&file.path);
+        let flag = |value: bool| if value { "yes" } else { "no" }.to_string();
+
+        match self {
+            Predicate::In(_) | Predicate::Path(_) => file.path.clone(),
+            Predicate::Name(_) => path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+            Predicate::Ext(_) => path.extension()
+                .map(|ext| ext.to_string_lossy().to_string())
+                .unwrap_or_else(|| "(none)".to_string()),
+            Predicate::Type(_) => file.file_type.clone(),
+            Predicate::Older(_) | Predicate::Newer(_) => format!("{} ({})", format_age(basis.age_secs(file, now_secs)), basis),
+            Predicate::Larger(_) | Predicate::Smaller(_) => format_file_size(file.size),
+            Predicate::Hidden => flag(file.is_hidden),
+            Predicate::Readonly => flag(file.is_readonly),
+            Predicate::Executable => flag(file.is_executable),
+        }
+    }
+
+    fn expected(&self) -> String {
+        match self {
+            Predicate::In(dir) => format!("under {}", dir.display()),
+            Predicate::Path(pattern) => format!("path matching {}", pattern),
+            Predicate::Name(pattern) => format!("name matching {}", pattern),
+            Predicate::Ext(extensions) => format!("one of {}", extensions.join(", ")),
+            Predicate::Type(file_type) => format!("type containing {}", file_type),
+            Predicate::Older(secs) => format!("at least {}", format_age(*secs)),
+            Predicate::Newer(secs) => format!("less than {}", format_age(*secs)),
+            Predicate::Larger(bytes) => format!("more than {}", format_file_size(*bytes)),
+            Predicate::Smaller(bytes) => format!("less than {}", format_file_size(*bytes)),
+            Predicate::Hidden | Predicate::Readonly | Predicate::Executable => "yes".to_string(),
+        }
+    }
+}
+
+// Written back in the expression syntax, for explanations
+impl std::fmt::Display for Predicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Predicate::In(dir) => write!(f, "in:{}", dir.display()),
+            Predicate::Path(pattern) => write!(f, "path:{}", pattern),
+            Predicate::Name(pattern) => write!(f, "name:{}", pattern),
+            Predicate::Ext(extensions) => write!(f, "ext:{}", extensions.join(",")),
+            Predicate::Type(file_type) => write!(f, "type:{}", file_type),
+            Predicate::Older(secs) => write!(f, "older:{}", age_syntax(*secs)),
+            Predicate::Newer(secs) => write!(f, "newer:{}", age_syntax(*secs)),
+            Predicate::Larger(bytes) => write!(f, "larger:{}", size_syntax(*bytes)),
+            Predicate::Smaller(bytes) => write!(f, "smaller:{}", size_syntax(*bytes)),
+            Predicate::Hidden => write!(f, "hidden"),
+            Predicate::Readonly => write!(f, "readonly"),
+            Predicate::Executable => write!(f, "executable"),
+        }
+    }
+}
+
+/// Parses an expression, with a message pointing at the problem if it is
+/// not valid.
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let tokens = tokenize(text)?;
+    if tokens.is_empty() {
+        return Err("the expression is empty".to_string());
+    }
+
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some(Token::Close) => Err("unmatched ')'".to_string()),
+        Some(token) => Err(format!("unexpected '{}'", token)),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Word(word) => write!(f, "{}", word),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            _ => {
+                // A word runs to the next space or parenthesis outside quotes
+                let mut word = String::new();
+                let mut quoted = false;
+                while let Some(&c) = chars.peek() {
+                    if !quoted && (c.is_whitespace() || c == '(' || c == ')') {
+                        break;
+                    }
+                    chars.next();
+                    if c == '"' {
+                        quoted = !quoted;
+                    } else {
+                        word.push(c);
+                    }
+                }
+                if quoted {
+                    return Err("missing closing '\"'".to_string());
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut terms = vec![self.and()?];
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            terms.push(self.and()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { Expr::Or(terms) })
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut terms = vec![self.unary()?];
+        loop {
+            if self.peek_keyword("and") {
+                self.pos += 1;
+            } else if self.peek_keyword("or") || matches!(self.tokens.get(self.pos), None | Some(Token::Close)) {
+                break;
+            }
+            terms.push(self.unary()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { Expr::And(terms) })
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+
+        match token {
+            None => Err("the expression ends too early".to_string()),
+            Some(Token::Close) => Err("unexpected ')'".to_string()),
+            Some(Token::Open) => {
+                let inner = self.or()?;
+                if self.tokens.get(self.pos) != Some(&Token::Close) {
+                    return Err("missing ')'".to_string());
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("not") => {
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("and") || word.eq_ignore_ascii_case("or") => {
+                Err(format!("'{}' needs something on both sides", word))
+            }
+            Some(Token::Word(word)) => parse_predicate(&word).map(Expr::Predicate),
+        }
+    }
+}
+
+fn parse_predicate(word: &str) -> Result<Predicate, String> {
+    match word.to_lowercase().as_str() {
+        "hidden" => return Ok(Predicate::Hidden),
+        "readonly" => return Ok(Predicate::Readonly),
+        "executable" => return Ok(Predicate::Executable),
+        _ => {}
+    }
+
+    let (key, value) = word.split_once(':')
+        .ok_or_else(|| format!("'{}' is not a predicate; expected something like name:*.iso", word))?;
+    if value.is_empty() {
+        return Err(format!("'{}:' needs a value", key));
+    }
+
+    match key.to_lowercase().as_str() {
+        "in" => Ok(Predicate::In(expand_home(value))),
+        "path" => Ok(Predicate::Path(value.to_string())),
+        "name" => Ok(Predicate::Name(value.to_string())),
+        "ext" => Ok(Predicate::Ext(
+            value.split(',')
+                .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+                .filter(|ext| !ext.is_empty())
+                .collect(),
+        )),
+        "type" => Ok(Predicate::Type(value.to_lowercase())),
+        "older" => parse_age(value).map(Predicate::Older),
+        "newer" => parse_age(value).map(Predicate::Newer),
+        "larger" => parse_size(value).map(Predicate::Larger),
+        "smaller" => parse_size(value).map(Predicate::Smaller),
+        other => Err(format!("unknown predicate '{}:'", other)),
+    }
+}
+
+// Component-wise, ignoring "." so in:Downloads matches ./Downloads/a.txt
+fn under(path: &Path, dir: &Path) -> bool {
+    let mut path = path.components().filter(|c| *c != Component::CurDir);
+    dir.components()
+        .filter(|c| *c != Component::CurDir)
+        .all(|component| path.next() == Some(component))
+}
+
+fn expand_home(dir: &str) -> PathBuf {
+    match dir.strip_prefix('~') {
+        Some("") => home_dir(),
+        Some(rest) if rest.starts_with('/') => home_dir().join(&rest[1..]),
+        _ => PathBuf::from(dir),
+    }
+}
+
+// "30d" -> seconds; bare numbers are days like --age
+fn parse_age(value: &str) -> Result<u64, String> {
+    let (number, unit) = split_unit(value);
+    let multiplier = match unit.to_lowercase().as_str() {
+        "h" => 3600,
+        "" | "d" => 86400,
+        "w" => 7 * 86400,
+        "y" => 365 * 86400,
+        _ => return Err(format!("'{}' is not an age; use h, d, w or y, e.g. 30d", value)),
+    };
+    number.parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("'{}' is not an age; use h, d, w or y, e.g. 30d", value))
+}
+
+// "1G" -> bytes; bare numbers are MB like --min-size
+fn parse_size(value: &str) -> Result<u64, String> {
+    let (number, unit) = split_unit(value);
+    let shift = match unit.to_uppercase().trim_end_matches('B') {
+        "" if unit.is_empty() => 20,
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("'{}' is not a size; use K, M, G or T, e.g. 10M", value)),
+    };
+    // A shift would drop the high bits instead of failing
+    number.parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("'{}' is not a size; use K, M, G or T, e.g. 10M", value))
+}
+
+fn age_syntax(secs: u64) -> String {
+    if secs.is_multiple_of(86400) { format!("{}d", secs / 86400) } else { format!("{}h", secs / 3600) }
+}
+
+fn size_syntax(bytes: u64) -> String {
+    let (shift, unit) = [(40, "T"), (30, "G"), (20, "M"), (10, "K")]
+        .into_iter()
+        .find(|(shift, _)| bytes != 0 && bytes.is_multiple_of(1 << shift))
+        .unwrap_or((0, "B"));
+    format!("{}{}", bytes >> shift, unit)
+}
+
+fn split_unit(value: &str) -> (&str, &str) {
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    value.split_at(split)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64, age_days: u64) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            size,
+            last_accessed: String::new(),
+            last_access_secs: 1_000_000_000 - age_days * 86400,
+            last_modified: String::new(),
+            last_modified_secs: 0,
+            last_changed_secs: 0,
+            created_secs: None,
+            atime_mode: Default::default(),
+            file_type: "Disk Image".to_string(),
+            is_hidden: false,
+            is_readonly: false,
+            is_executable: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let expr = parse("in:/a older:30d or not hidden").unwrap();
+        assert_eq!(expr, Expr::Or(vec![
+            Expr::And(vec![
+                Expr::Predicate(Predicate::In("/a".into())),
+                Expr::Predicate(Predicate::Older(30 * 86400)),
+            ]),
+            Expr::Not(Box::new(Expr::Predicate(Predicate::Hidden))),
+        ]));
+
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+        assert_eq!(parse_size("512b"), Ok(512));
+        assert_eq!(parse_size("5"), Ok(5 << 20));
+        assert!(parse("(name:*.iso").is_err());
+        assert!(parse("name:*.iso or").is_err());
+        assert!(parse("bigger:1G").is_err());
+        assert!(parse("older:soon").is_err());
+        assert!(parse("older:999999999999999y").is_err());
+        assert!(parse("larger:16777216T").is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let now = 1_000_000_000;
+        let expr = parse("(in:/home/me/Downloads older:30d or name:*.iso larger:1G) and not in:\"/home/me/keep\"").unwrap();
+
+        assert!(expr.matches(&file("/home/me/Downloads/setup.exe", 10, 45), now, AgeBasis::Access));
+        assert!(!expr.matches(&file("/home/me/Downloads/setup.exe", 10, 5), now, AgeBasis::Access));
+        assert!(expr.matches(&file("/data/ubuntu.iso", 2 << 30, 0), now, AgeBasis::Access));
+        assert!(!expr.matches(&file("/home/me/keep/ubuntu.iso", 2 << 30, 0), now, AgeBasis::Access));
+        assert!(!expr.matches(&file("/home/me/Downloads2/old.zip", 10, 45), now, AgeBasis::Access));
+        let checks = &mut Vec::new();
+        assert!(!expr.trace(&file("/home/me/keep/ubuntu.iso", 2 << 30, 0), now, AgeBasis::Access, 0, checks));
+        let names: Vec<&str> = checks.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["all of", "any of", "all of", "in:/home/me/Downloads", "older:30d", "all of", "name:*.iso", "larger:1G", "not", "in:/home/me/keep"]);
+        assert_eq!(checks.iter().map(|c| c.passed).filter(|&p| p).count(), 5);
+
+        assert!(parse("in:./Downloads").unwrap().matches(&file("Downloads/a.iso", 1, 0), now, AgeBasis::Access));
+        assert!(parse("ext:ISO,.img type:disk").unwrap().matches(&file("/x/a.iso", 1, 0), now, AgeBasis::Access));
+
+        // Modified at the epoch, so old by that measure however recently read
+        let recent = file("/x/a.iso", 1, 0);
+        assert!(!parse("older:1y").unwrap().matches(&recent, now, AgeBasis::Access));
+        assert!(parse("older:1y").unwrap().matches(&recent, now, AgeBasis::Modify));
+        assert!(parse("older:1y").unwrap().matches(&recent, now, AgeBasis::Oldest));
+        assert!(!parse("older:1y").unwrap().matches(&recent, now, AgeBasis::Newest));
+    }
+}
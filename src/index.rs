@@ -0,0 +1,199 @@
+//! Scan results kept on disk between runs, one index per scanned folder.
+//!
+//! Adding, removing or renaming a file changes its directory's mtime, so a
+//! rescan through [`scan_folder_indexed`] only reads directories whose mtime
+//! differs from the index and takes everything else from it. Edits inside
+//! a file and reads of it leave the directory alone, so the sizes and
+//! timestamps of files in unchanged directories come from the index; a full
+//! scan runs once the index is a day old to bring them up to date. Access
+//! times on relatime mounts only move once a day anyway.
+
+use crate::actions::{data_dir, FileActionError};
+use crate::protect::ProtectedPaths;
+use crate::scanner::{FileInfo, ScanOptions, ScanProgress};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// How old an index may get before the next scan reads everything again
+const FULL_SCAN_INTERVAL_SECS: i64 = 86400;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScanIndex {
+    /// The scanned folder, as an absolute path.
+    pub root: String,
+    /// The scan options and protected paths the index was built with; any
+    /// change to them means a full scan.
+    pub fingerprint: String,
+    /// When the last scan started, in seconds since the epoch.
+    pub scanned_at: i64,
+    /// When the last full scan started.
+    pub full_scan_at: i64,
+    pub(crate) dirs: HashMap<String, DirRecord>,
+}
+
+/// One directory as last read: its mtime and the entries the scan kept.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct DirRecord {
+    pub(crate) mtime: (i64, i64),
+    pub(crate) entries: Vec<IndexEntry>,
+}
+
+/// A subdirectory (no `file`) or a file that passed the scan filters.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct IndexEntry {
+    /// Position in the directory listing, for ordered scans.
+    pub(crate) position: u32,
+    pub(crate) name: String,
+    pub(crate) file: Option<FileInfo>,
+}
+
+/// Where indexes are kept.
+pub fn index_dir() -> PathBuf {
+    data_dir().join("index")
+}
+
+/// The index file for a scanned folder.
+pub fn index_path(folder: &str) -> PathBuf {
+    index_dir().join(format!("{:016x}.json.gz", fnv1a(absolute_root(folder).as_bytes())))
+}
+
+impl ScanIndex {
+    pub fn load(path: &Path) -> Result<Self, FileActionError> {
+        let reader = GzDecoder::new(BufReader::new(File::open(path)?));
+        serde_json::from_reader(reader).map_err(|e| FileActionError::Other(format!("Not a valid scan index: {}", e)))
+    }
+
+    /// Writes the index beside its final place and renames it in.
+    pub fn save(&self, path: &Path) -> Result<(), FileActionError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let written = File::create(&temp).map_err(FileActionError::from).and_then(|file| {
+            let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::fast());
+            serde_json::to_writer(&mut encoder, self).map_err(|e| FileActionError::Other(e.to_string()))?;
+            encoder.finish()?.flush()?;
+            Ok(())
+        });
+        match written.and_then(|_| fs::rename(&temp, path).map_err(FileActionError::from)) {
+            Ok(()) => Ok(()),
+            Err(error) => {
+                let _ = fs::remove_file(&temp);
+                Err(error)
+            }
+        }
+    }
+}
+
+/// [`scan_folder_with_progress`](crate::scanner::scan_folder_with_progress),
+/// reusing the folder's index for directories that have not changed and
+/// saving the refreshed index afterwards. A cancelled scan leaves the index
+/// as it was.
+pub fn scan_folder_indexed<F>(
+    folder: &str,
+    options: &ScanOptions,
+    cancel: &AtomicBool,
+    on_progress: F,
+) -> Vec<FileInfo>
+where
+    F: FnMut(&ScanProgress),
+{
+    let path = index_path(folder);
+    let root = absolute_root(folder);
+    let fingerprint = fingerprint(options);
+    let now = chrono::Utc::now().timestamp();
+
+    let mut index = ScanIndex::load(&path)
+        .ok()
+        .filter(|index| index.root == root && index.fingerprint == fingerprint)
+        .filter(|index| now - index.full_scan_at < FULL_SCAN_INTERVAL_SECS)
+        .unwrap_or_else(|| ScanIndex { root, fingerprint, full_scan_at: now, ..Default::default() });
+
+    let files = crate::walker::walk(folder, options, cancel, on_progress, Some(&mut index));
+    if !cancel.load(Ordering::Relaxed) {
+        // Without an index the next scan is simply a full one
+        let _ = index.save(&path);
+    }
+    files
+}
+
+/// Removes a folder's index, so its next scan reads everything.
+pub fn forget(folder: &str) -> Result<(), FileActionError> {
+    match fs::remove_file(index_path(folder)) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+fn absolute_root(folder: &str) -> String {
+    std::path::absolute(folder)
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| folder.to_string())
+}
+
+// Everything that decides which files a scan keeps; threads and ordering
+// only change how they are found
+fn fingerprint(options: &ScanOptions) -> String {
+    format!(
+        "hidden={} system={} depth={:?} symlinks={} ext={:?} exclude={:?} protected={:?}",
+        options.include_hidden,
+        options.include_system,
+        options.max_depth,
+        options.follow_symlinks,
+        options.file_extensions,
+        options.exclude_patterns,
+        ProtectedPaths::load().paths(),
+    )
+}
+
+// A stable hash for index file names; std's hasher may change between releases
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incremental_rescan() {
+        let dir = std::env::temp_dir().join(format!("trashdoctor-index-{}", std::process::id()));
+        fs::create_dir_all(dir.join("same")).unwrap();
+        fs::create_dir_all(dir.join("changed")).unwrap();
+        fs::write(dir.join("same/a.txt"), "a").unwrap();
+        fs::write(dir.join("changed/b.txt"), "b").unwrap();
+        let folder = dir.to_str().unwrap();
+        let options = ScanOptions::default();
+        let cancel = AtomicBool::new(false);
+
+        // Pretend the first scan happened a minute after everything was made
+        let mut index = ScanIndex::default();
+        let first = crate::walker::walk(folder, &options, &cancel, |_| {}, Some(&mut index));
+        index.scanned_at += 60;
+        let same = dir.join("same").display().to_string();
+        let modified = fs::metadata(dir.join("same")).unwrap().modified().unwrap();
+        let modified = modified.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+        assert_eq!(index.dirs[&same].mtime.0, modified);
+
+        // A file the index says is there shows up without being read, and
+        // a directory that changed is read again
+        index.dirs.get_mut(&same).unwrap().entries[0].file.as_mut().unwrap().size = 999;
+        fs::write(dir.join("changed/c.txt"), "c").unwrap();
+        let second = crate::walker::walk(folder, &options, &cancel, |_| {}, Some(&mut index));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(first.len(), 2);
+        assert_eq!(second.len(), 3);
+        assert_eq!(second.iter().find(|f| f.path.ends_with("a.txt")).unwrap().size, 999);
+        assert_ne!(index_path("/a"), index_path("/b"));
+    }
+}
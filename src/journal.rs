@@ -0,0 +1,448 @@
+use crate::actions::{data_dir, move_path, FileActionError};
+use crate::archive;
+use crate::trashcan;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Delete,
+    Archive,
+    Trash,
+    Link,
+    /// Marks an entry of an earlier batch as restored, or as one that never
+    /// can be. One with an empty path settles its whole batch.
+    Undo,
+}
+
+/// How far the action on an entry got. A file is recorded as
+/// [`Intent`](EntryState::Intent) before it is touched, then again as done
+/// or failed; the last line for a file wins.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryState {
+    Intent,
+    /// Journals written before intents were recorded only have these.
+    #[default]
+    Done,
+    /// The action failed and left the file where it was.
+    Failed,
+}
+
+/// One line of the journal: a single file touched by a batch.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub batch: u64,
+    pub timestamp: String,
+    pub operation: Operation,
+    #[serde(default)]
+    pub state: EntryState,
+    pub original_path: String,
+    pub destination: Option<String>,
+    pub size: u64,
+    pub mtime: u64,
+    pub hash: Option<String>,
+}
+
+/// What [`undo_last_batch`] managed to put back.
+#[derive(Clone, Debug, Default)]
+pub struct UndoReport {
+    pub batch: u64,
+    pub operation: Option<Operation>,
+    pub restored: Vec<String>,
+    pub skipped: Vec<(String, String)>,
+    /// How many of the skipped files had something in their way. They stay
+    /// pending, and the next undo tries them again unless the batch is given
+    /// up with [`skip_last_batch`].
+    pub blocked: usize,
+}
+
+/// An open batch. Each file is recorded with [`intend`](Batch::intend)
+/// before it is touched and with [`record`](Batch::record) or
+/// [`fail`](Batch::fail) afterwards, so an interrupted batch still shows
+/// every file it may have moved.
+pub struct Batch {
+    id: u64,
+    operation: Operation,
+    file: File,
+}
+
+impl Batch {
+    /// Opens the journal for a new batch, failing before anything is touched
+    /// if it cannot be written.
+    pub fn begin(operation: Operation) -> Result<Self, FileActionError> {
+        Ok(Self {
+            id: new_batch_id(),
+            operation,
+            file: open_journal()?,
+        })
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Records that a file is about to be acted on. Nothing should touch
+    /// the file if this fails.
+    pub fn intend(&mut self, original_path: &str, size: u64, mtime: u64) -> Result<(), FileActionError> {
+        self.write(EntryState::Intent, original_path, None, size, mtime, None)
+    }
+
+    /// Confirms that the action on a file went through.
+    pub fn record(
+        &mut self,
+        original_path: &str,
+        destination: Option<String>,
+        size: u64,
+        mtime: u64,
+        hash: Option<String>,
+    ) -> Result<(), FileActionError> {
+        self.write(EntryState::Done, original_path, destination, size, mtime, hash)
+    }
+
+    /// Withdraws an intent after the action failed.
+    pub fn fail(&mut self, original_path: &str) -> Result<(), FileActionError> {
+        self.write(EntryState::Failed, original_path, None, 0, 0, None)
+    }
+
+    fn write(
+        &mut self,
+        state: EntryState,
+        original_path: &str,
+        destination: Option<String>,
+        size: u64,
+        mtime: u64,
+        hash: Option<String>,
+    ) -> Result<(), FileActionError> {
+        let entry = JournalEntry {
+            batch: self.id,
+            timestamp: chrono::Local::now().to_rfc3339(),
+            operation: self.operation,
+            state,
+            original_path: original_path.to_string(),
+            destination,
+            size,
+            mtime,
+            hash,
+        };
+        append(&mut self.file, &entry)
+    }
+}
+
+// The time in milliseconds, then three digits of the process id and three of
+// a per-process count, so batches started in the same millisecond by the GUI
+// and the command line, or twice by one process, stay apart
+fn new_batch_id() -> u64 {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    let millis = chrono::Utc::now().timestamp_millis() as u64;
+    let process = u64::from(std::process::id()) % 1000;
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed) % 1000;
+    millis * 1_000_000 + process * 1000 + sequence
+}
+
+pub fn journal_path() -> PathBuf {
+    data_dir().join("journal.jsonl")
+}
+
+pub fn read_journal() -> Result<Vec<JournalEntry>, FileActionError> {
+    let file = match File::open(journal_path()) {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+
+    // A torn final line from a crash is skipped rather than failing the read
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
+
+/// Entries of the most recent batch that has not been undone yet.
+pub fn last_batch() -> Result<Vec<JournalEntry>, FileActionError> {
+    Ok(pending_batch(read_journal()?))
+}
+
+// A batch stays pending while any of its entries has not been settled by
+// an undo, so files skipped because something was in the way are tried
+// again before any older batch
+fn pending_batch(entries: Vec<JournalEntry>) -> Vec<JournalEntry> {
+    let undos = entries.iter().filter(|e| e.operation == Operation::Undo);
+    let closed: HashSet<u64> = undos.clone()
+        .filter(|e| e.original_path.is_empty())
+        .map(|e| e.batch)
+        .collect();
+    let undone: HashSet<(u64, String)> = undos
+        .map(|e| (e.batch, e.original_path.clone()))
+        .collect();
+
+    // The last line for each file says how far its action got
+    let mut latest: Vec<JournalEntry> = Vec::new();
+    let mut positions: HashMap<(u64, String), usize> = HashMap::new();
+    for entry in entries.into_iter().filter(|e| e.operation != Operation::Undo) {
+        let key = (entry.batch, entry.original_path.clone());
+        match positions.get(&key) {
+            Some(&position) => latest[position] = entry,
+            None => {
+                positions.insert(key, latest.len());
+                latest.push(entry);
+            }
+        }
+    }
+    let pending = |e: &JournalEntry| {
+        e.state != EntryState::Failed
+            && !closed.contains(&e.batch)
+            && !undone.contains(&(e.batch, e.original_path.clone()))
+    };
+
+    let Some(batch) = latest.iter().rev().find(|e| pending(e)).map(|e| e.batch) else {
+        return Vec::new();
+    };
+    latest.iter()
+        .filter(|e| e.batch == batch && pending(e))
+        .cloned()
+        .collect()
+}
+
+/// Reverses the most recent batch that has not been undone.
+///
+/// Archived and trashed files are moved back to where they came from, and
+/// linked duplicates get an independent copy again. Deleted files cannot be
+/// brought back and are reported as skipped, as are files whose original
+/// location is occupied. Those are left pending, so the next undo tries
+/// them again once the location is free, until [`skip_last_batch`] gives
+/// them up.
+pub fn undo_last_batch() -> Result<UndoReport, FileActionError> {
+    let entries = last_batch()?;
+    let mut report = UndoReport::default();
+    let Some(first) = entries.first() else {
+        return Ok(report);
+    };
+    report.batch = first.batch;
+    report.operation = Some(first.operation);
+
+    let mut journal = open_journal()?;
+
+    for entry in entries {
+        let settled = match restore_entry(&entry) {
+            Ok(()) => {
+                report.restored.push(entry.original_path.clone());
+                true
+            }
+            Err(Skip::Never(reason)) => {
+                report.skipped.push((entry.original_path.clone(), reason));
+                true
+            }
+            Err(Skip::Blocked(reason)) => {
+                report.skipped.push((entry.original_path.clone(), reason));
+                report.blocked += 1;
+                false
+            }
+        };
+        if settled {
+            append(&mut journal, &JournalEntry {
+                timestamp: chrono::Local::now().to_rfc3339(),
+                operation: Operation::Undo,
+                ..entry
+            })?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Gives up on whatever is left of the most recent pending batch, such as
+/// files whose original location stays occupied, so the next undo moves on
+/// to the batch before it. Returns the entries given up.
+pub fn skip_last_batch() -> Result<Vec<JournalEntry>, FileActionError> {
+    let entries = last_batch()?;
+    if let Some(first) = entries.first() {
+        append(&mut open_journal()?, &JournalEntry {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            operation: Operation::Undo,
+            original_path: String::new(),
+            destination: None,
+            ..first.clone()
+        })?;
+    }
+    Ok(entries)
+}
+
+// Why an entry was not put back
+enum Skip {
+    // It never can be, so it is settled and the next undo moves on
+    Never(String),
+    // Something is in the way; the next undo tries again
+    Blocked(String),
+}
+
+fn restore_entry(entry: &JournalEntry) -> Result<(), Skip> {
+    let original = Path::new(&entry.original_path);
+
+    match entry.operation {
+        Operation::Delete => Err(Skip::Never("Deleted files cannot be restored".to_string())),
+        Operation::Undo => Ok(()),
+        // Interrupted before the move was confirmed, so where it went is unknown
+        Operation::Archive | Operation::Trash if entry.state == EntryState::Intent => Err(Skip::Never(
+            if original.exists() {
+                "The batch stopped before this file was moved".to_string()
+            } else {
+                "The batch stopped while this file was moved; look for it in the archive or trash".to_string()
+            }
+        )),
+        Operation::Archive | Operation::Trash => {
+            let destination = entry.destination.as_deref()
+                .ok_or_else(|| Skip::Never("No destination was recorded".to_string()))?;
+            if entry.operation == Operation::Archive {
+                if let Ok(Some(archived)) = archive::find_by_location(destination) {
+                    return archive::restore(&archived.id, None).map(|_| ()).map_err(|error| match error {
+                        FileActionError::FileNotFound => Skip::Never("No longer in the archive or trash".to_string()),
+                        error => Skip::Blocked(error.to_string()),
+                    });
+                }
+            }
+            if !Path::new(destination).exists() {
+                return Err(Skip::Never("No longer in the archive or trash".to_string()));
+            }
+            if original.exists() {
+                return Err(Skip::Blocked("Original location is occupied".to_string()));
+            }
+            if let Some(parent) = original.parent() {
+                fs::create_dir_all(parent).map_err(|e| Skip::Blocked(e.to_string()))?;
+            }
+            move_path(Path::new(destination), original).map_err(|e| Skip::Blocked(e.to_string()))?;
+
+            if entry.operation == Operation::Trash {
+                trashcan::forget(Path::new(destination));
+            }
+            Ok(())
+        }
+        Operation::Link => {
+            // Contents are identical, so an independent copy undoes the link
+            let file_name = original.file_name()
+                .ok_or_else(|| Skip::Never("Invalid file path".to_string()))?
+                .to_string_lossy();
+            let temp_path = original.with_file_name(format!(".{}.trashdoctor-unlink", file_name));
+            fs::copy(original, &temp_path).map_err(|e| Skip::Blocked(e.to_string()))?;
+            fs::rename(&temp_path, original).map_err(|e| {
+                let _ = fs::remove_file(&temp_path);
+                Skip::Blocked(e.to_string())
+            })
+        }
+    }
+}
+
+fn open_journal() -> Result<File, FileActionError> {
+    let path = journal_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(OpenOptions::new().create(true).append(true).open(path)?)
+}
+
+fn append(file: &mut File, entry: &JournalEntry) -> Result<(), FileActionError> {
+    let mut line = serde_json::to_string(entry)
+        .map_err(|e| FileActionError::Other(e.to_string()))?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(batch: u64, operation: Operation, path: &str) -> JournalEntry {
+        JournalEntry {
+            batch,
+            timestamp: String::new(),
+            operation,
+            state: EntryState::Done,
+            original_path: path.to_string(),
+            destination: None,
+            size: 0,
+            mtime: 0,
+            hash: None,
+        }
+    }
+
+    #[test]
+    fn test_pending_batch_skips_undone() {
+        let entries = vec![
+            entry(1, Operation::Archive, "/a"),
+            entry(1, Operation::Archive, "/b"),
+            entry(2, Operation::Trash, "/c"),
+            entry(2, Operation::Undo, "/c"),
+        ];
+        let pending = pending_batch(entries);
+        assert_eq!(pending.len(), 2);
+        assert!(pending.iter().all(|e| e.batch == 1));
+
+        assert!(pending_batch(vec![entry(3, Operation::Delete, "/d"), entry(3, Operation::Undo, "")]).is_empty());
+
+        // A failed file was never moved, and an intent is replaced by its outcome
+        let intent = |path| JournalEntry { state: EntryState::Intent, ..entry(4, Operation::Trash, path) };
+        let pending = pending_batch(vec![
+            intent("/e"),
+            intent("/f"),
+            JournalEntry { state: EntryState::Failed, ..entry(4, Operation::Trash, "/e") },
+            entry(4, Operation::Trash, "/f"),
+        ]);
+        assert_eq!(pending.len(), 1);
+        assert_eq!((pending[0].original_path.as_str(), pending[0].state), ("/f", EntryState::Done));
+    }
+
+    #[test]
+    fn test_partial_undo_retries_blocked() {
+        let dir = std::env::temp_dir().join(format!("trashdoctor-undo-{}", std::process::id()));
+        fs::create_dir_all(dir.join("trash")).unwrap();
+        let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+        let trashed = |path: &Path| dir.join("trash").join(path.file_name().unwrap());
+        let mut entries = Vec::new();
+        for path in [&a, &b] {
+            fs::write(trashed(path), "old").unwrap();
+            entries.push(JournalEntry {
+                destination: Some(trashed(path).display().to_string()),
+                ..entry(1, Operation::Trash, path.to_str().unwrap())
+            });
+        }
+        entries.insert(0, entry(0, Operation::Trash, "/older"));
+
+        // b's place is taken, so only a comes back and the batch stays pending
+        fs::write(&b, "new").unwrap();
+        let mut blocked = 0;
+        for entry in pending_batch(entries.clone()) {
+            match restore_entry(&entry) {
+                Ok(()) => entries.push(JournalEntry { operation: Operation::Undo, ..entry }),
+                Err(Skip::Blocked(_)) => blocked += 1,
+                Err(Skip::Never(reason)) => panic!("{}", reason),
+            }
+        }
+        assert_eq!(blocked, 1);
+        let pending = pending_batch(entries.clone());
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].original_path, b.to_str().unwrap());
+
+        // Once it is free, the next undo restores it before any older batch
+        fs::remove_file(&b).unwrap();
+        assert!(restore_entry(&pending[0]).is_ok());
+        entries.push(JournalEntry { operation: Operation::Undo, ..pending[0].clone() });
+        let contents = fs::read_to_string(&b).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(contents, "old");
+        assert_eq!(pending_batch(entries)[0].original_path, "/older");
+    }
+
+    #[test]
+    fn test_batch_ids_are_unique() {
+        let ids: HashSet<u64> = (0..100).map(|_| new_batch_id()).collect();
+        assert_eq!(ids.len(), 100);
+    }
+}
@@ -0,0 +1,28 @@
+//! TrashDoctor's cleanup engine.
+//!
+//! The scanner walks a folder into [`FileInfo`] records, the rules narrow
+//! them down with a [`RuleConfig`], and the actions delete, archive or trash
+//! the result, recording every batch in the undo [`journal`]. The GUI and
+//! command-line binaries are both built on this crate.
+
+pub mod scanner;
+mod walker;
+pub mod rules;
+pub mod expr;
+pub mod mounts;
+pub mod actions;
+pub mod archive;
+mod bundle;
+pub mod journal;
+pub mod trashcan;
+pub mod plan;
+pub mod protect;
+pub mod index;
+pub mod watch;
+pub mod tree;
+pub mod text;
+pub mod cli;
+
+pub use scanner::{FileInfo, ScanOptions, scan_folder, scan_folder_with_options};
+pub use rules::{AgeBasis, RuleConfig, SmartRule, apply_rules};
+pub use actions::{FileActionError, delete_file, archive_file, move_to_trash};
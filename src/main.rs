@@ -1,13 +1,23 @@
-mod scanner;
-mod rules;
-mod actions;
-
-use iced::{Application, Command, Element, executor, Settings, Theme, Length, widget::{column, row, scrollable, text, button, checkbox, text_input, container, progress_bar}, theme};
-use scanner::scan_folder;
-use scanner::FileInfo;
-use rules::{apply_rules, RuleConfig};
-use actions::{delete_file, archive_file};
+use iced::{Application, Command, Element, executor, Settings, Subscription, Theme, Length, subscription, widget::{column, row, scrollable, text, button, checkbox, text_input, container, progress_bar, pick_list}, theme};
+use iced::futures::{channel::mpsc, SinkExt, StreamExt};
+use trashdoctor::{cli, AgeBasis, FileInfo, FileActionError, ScanOptions, apply_rules, RuleConfig, SmartRule};
+use trashdoctor::expr;
+use trashdoctor::mounts::atime_warning;
+use trashdoctor::index::scan_folder_indexed;
+use trashdoctor::watch::{apply_changes, Change, Watcher};
+use trashdoctor::tree::DirTree;
+use trashdoctor::text::capitalize;
+use trashdoctor::rules::{explain, Explanation, load_saved_rules, save_rule, delete_saved_rule, get_predefined_rules, suggest_rules_for_files, mb_to_bytes};
+use trashdoctor::scanner::{file_info, skip_reason, get_duplicate_files, DuplicateGroup, KeepPolicy, ScanProgress};
+use trashdoctor::actions::{format_file_size, delete_files, archive_files_with, archive_paths_with, disk_usage, files_under, trash_files, purge_trash, link_duplicates, verify_duplicates, BatchOutcome, LinkMode};
+use trashdoctor::journal::{skip_last_batch, undo_last_batch};
+use trashdoctor::archive::{self, ArchiveEntry, ArchiveOptions};
+use trashdoctor::trashcan::{self, TrashEntry};
+use trashdoctor::plan::{self, Plan, Problem};
+use trashdoctor::journal::Operation;
 use rfd::FileDialog;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 #[derive(Debug, Clone)]
@@ -23,6 +33,8 @@ enum Message {
     SelectAll(bool),
     ConfirmDelete,
     CancelDelete,
+    ConfirmFolders,
+    CancelFolders,
     ShowDeleteConfirmation,
     SortBy(SortCriteria),
     FilterByType(String),
@@ -32,6 +44,175 @@ enum Message {
     ExportList,
     ToggleAutoRefresh(bool),
     AutoRefreshTick,
+    WatchReady(String),
+    FilesChanged(String, Vec<Change>),
+    WatchFailed(String, String),
+    ScanProgressed(u64, ScanProgress),
+    ScanFinished(u64, Vec<FileInfo>),
+    CancelScan,
+    TrashSelected,
+    ShowView(ViewMode),
+    FindDuplicates,
+    DuplicatesFound(Vec<DuplicateGroup>),
+    ToggleDuplicate(usize, usize, bool),
+    ApplyKeepPolicy(KeepPolicy),
+    ChangePreferredDir(String),
+    DedupeSelected,
+    UndoLast,
+    SkipUndo,
+    ToggleArchived(usize, bool),
+    OpenFolder(String),
+    ToggleFolderEntry(String, bool),
+    RestoreSelected,
+    RestoreSelectedTo,
+    RestoreTargetSelected(String),
+    ChangeCompression(u32),
+    ChangeArchiveDestination(String),
+    SelectArchiveDestination,
+    ToggleTrashed(usize, bool),
+    ChangePurgeAge(String),
+    SelectOlderThan,
+    ChangeTrashCap(String),
+    SelectOverCap,
+    ToggleDryRun(bool),
+    ExecutePlan,
+    ExportPlan,
+    PlanExportTarget(String),
+    DiscardPlan,
+    UseRule(RuleSource, usize),
+    ToggleRuleEditor,
+    EditRule(RuleField, String),
+    ToggleRuleFlag(RuleFlag, bool),
+    ChangeAgeBasis(AgeBasis),
+    UseModifiedAge,
+    ApplyRuleForm,
+    SaveRule,
+    DeleteRule,
+    ChangeExplainPath(String),
+    ExplainPath,
+    CloseDetails,
+}
+
+// The detail pane: a file and how the current rule judged it
+#[derive(Debug, Clone)]
+struct FileDetails {
+    file: FileInfo,
+    explanation: Explanation,
+    skipped_by_scan: Option<String>,
+}
+
+// Where a rule in the sidebar comes from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RuleSource {
+    Predefined,
+    Saved,
+    Suggested,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RuleField {
+    Name,
+    MaxAge,
+    MinSize,
+    MaxSize,
+    Types,
+    ExcludeTypes,
+    Patterns,
+    ExcludePatterns,
+    ArchiveTo,
+    Expression,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RuleFlag {
+    Hidden,
+    Readonly,
+    Executable,
+}
+
+// The rule editor's text boxes; lists are comma-separated and empty
+// optional fields mean "no limit"
+#[derive(Debug, Clone, Default)]
+struct RuleForm {
+    name: String,
+    max_age: String,
+    age_basis: AgeBasis,
+    min_size: String,
+    max_size: String,
+    types: String,
+    exclude_types: String,
+    patterns: String,
+    exclude_patterns: String,
+    archive_to: String,
+    expression: String,
+    include_hidden: bool,
+    include_readonly: bool,
+    include_executable: bool,
+}
+
+impl RuleForm {
+    fn from_rule(name: &str, config: &RuleConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            max_age: config.max_age_days.to_string(),
+            age_basis: config.age_basis,
+            min_size: config.min_size_mb.to_string(),
+            max_size: config.max_size_mb.map(|mb| mb.to_string()).unwrap_or_default(),
+            types: config.file_types.as_deref().unwrap_or_default().join(", "),
+            exclude_types: config.exclude_file_types.as_deref().unwrap_or_default().join(", "),
+            patterns: config.custom_patterns.join(", "),
+            exclude_patterns: config.exclude_patterns.join(", "),
+            archive_to: config.archive_destination.clone().unwrap_or_default(),
+            expression: config.expression.clone().unwrap_or_default(),
+            include_hidden: config.include_hidden,
+            include_readonly: config.include_readonly,
+            include_executable: config.include_executable,
+        }
+    }
+
+    fn to_config(&self) -> Result<RuleConfig, String> {
+        let number = |label: &str, value: &str| -> Result<u64, String> {
+            value.trim().parse().map_err(|_| format!("{} must be a whole number.", label))
+        };
+        let list = |value: &str| -> Vec<String> {
+            value.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+        };
+        let optional_list = |value: &str| Some(list(value)).filter(|items| !items.is_empty());
+        let expression = Some(self.expression.trim().to_string()).filter(|text| !text.is_empty());
+        if let Some(text) = &expression {
+            expr::parse(text).map_err(|e| format!("The expression is not valid: {}.", e))?;
+        }
+
+        Ok(RuleConfig {
+            max_age_days: number("Max age", &self.max_age)?,
+            age_basis: self.age_basis,
+            min_size_mb: number("Min size", &self.min_size)?,
+            max_size_mb: match self.max_size.trim() {
+                "" => None,
+                value => Some(number("Max size", value)?),
+            },
+            file_types: optional_list(&self.types),
+            exclude_file_types: optional_list(&self.exclude_types),
+            include_hidden: self.include_hidden,
+            include_readonly: self.include_readonly,
+            include_executable: self.include_executable,
+            custom_patterns: list(&self.patterns),
+            exclude_patterns: list(&self.exclude_patterns),
+            archive_destination: Some(self.archive_to.trim().to_string()).filter(|dir| !dir.is_empty()),
+            expression,
+        })
+    }
+}
+
+static COMPRESSION_LEVELS: [u32; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+
+#[derive(Debug, Clone, PartialEq)]
+enum ViewMode {
+    Files,
+    Folders,
+    Duplicates,
+    Archive,
+    Trash,
 }
 
 #[derive(Debug, Clone)]
@@ -46,10 +227,29 @@ enum SortCriteria {
 enum AppState {
     Normal,
     ConfirmingDelete,
+    // Archiving or trashing whole directories, which take more than the
+    // scan listed
+    ConfirmingFolders(Operation),
     Processing,
+    Scanning,
+    ReviewingPlan,
+}
+
+// A background scan; its id ties progress messages to the scan that sent them
+struct ScanJob {
+    id: u64,
+    cancel: Arc<AtomicBool>,
+    progress: ScanProgress,
+    announce: bool,
 }
 
 pub fn main() -> iced::Result {
+    // Any arguments switch to the headless command-line interface
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     TrashDoctor::run(Settings::default())
 }
 
@@ -67,9 +267,45 @@ struct TrashDoctor {
     sort_by: SortCriteria,
     filter_by_type: String,
     auto_refresh: bool,
+    // Set when the folder cannot be watched and auto-refresh scans on a timer
+    watch_fallback: bool,
+    // What the watcher saw while a scan was running, applied over its results
+    pending_changes: Vec<Change>,
+    // Directory totals for the Folders view, the directory being shown and
+    // the files and directories ticked there
+    dir_tree: Option<DirTree>,
+    folder_view: String,
+    folder_selected: Vec<String>,
     stats: FileStats,
     selected_count: usize,
     total_size_selected: u64,
+    scan: Option<ScanJob>,
+    next_scan_id: u64,
+    view_mode: ViewMode,
+    duplicates: Vec<DuplicateGroup>,
+    duplicate_selected: Vec<Vec<bool>>,
+    finding_duplicates: bool,
+    preferred_dir: String,
+    archive_entries: Vec<ArchiveEntry>,
+    archive_selected: Vec<bool>,
+    compression_level: u32,
+    archive_destination: String,
+    trash_entries: Vec<TrashEntry>,
+    trash_selected: Vec<bool>,
+    purge_age: String,
+    trash_cap: String,
+    dry_run: bool,
+    // The last undo left files pending because something was in the way
+    undo_blocked: bool,
+    plan: Option<Plan>,
+    predefined_rules: Vec<SmartRule>,
+    saved_rules: Vec<SmartRule>,
+    suggested_rules: Vec<SmartRule>,
+    rule_form: RuleForm,
+    show_rule_editor: bool,
+    details: Option<FileDetails>,
+    explain_path: String,
+    atime_warning: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -98,13 +334,18 @@ impl Application for TrashDoctor {
 
     fn new(_flags: ()) -> (Self, Command<Message>) {
         let folder = String::from("/home");
+        let (saved_rules, rule_errors) = load_saved_rules();
+        let (message, message_type) = match rule_errors.first() {
+            Some(err) => (format!("Some saved rules could not be read: {}", err), MessageType::Warning),
+            None => ("Select a folder to begin scanning for old files.".to_string(), MessageType::Info),
+        };
         (
             TrashDoctor {
                 files: vec![],
                 all_files: vec![],
                 selected: vec![],
-                message: "Select a folder to begin scanning for old files.".to_string(),
-                message_type: MessageType::Info,
+                message,
+                message_type,
                 folder_path: folder,
                 age_filter: "30".into(),
                 size_filter: "100".into(),
@@ -113,9 +354,40 @@ impl Application for TrashDoctor {
                 sort_by: SortCriteria::Date,
                 filter_by_type: "All".to_string(),
                 auto_refresh: false,
+                watch_fallback: false,
+                pending_changes: vec![],
+                dir_tree: None,
+                folder_view: String::new(),
+                folder_selected: vec![],
                 stats: FileStats::default(),
                 selected_count: 0,
                 total_size_selected: 0,
+                scan: None,
+                next_scan_id: 0,
+                view_mode: ViewMode::Files,
+                duplicates: vec![],
+                duplicate_selected: vec![],
+                finding_duplicates: false,
+                preferred_dir: String::new(),
+                archive_entries: vec![],
+                archive_selected: vec![],
+                compression_level: ArchiveOptions::default().compression_level,
+                archive_destination: String::new(),
+                trash_entries: vec![],
+                trash_selected: vec![],
+                purge_age: "30".into(),
+                trash_cap: "1024".into(),
+                dry_run: false,
+                undo_blocked: false,
+                plan: None,
+                predefined_rules: get_predefined_rules(),
+                saved_rules,
+                suggested_rules: vec![],
+                rule_form: RuleForm::from_rule("", &RuleConfig { max_age_days: 30, min_size_mb: 100, ..Default::default() }),
+                show_rule_editor: false,
+                details: None,
+                explain_path: String::new(),
+                atime_warning: None,
             },
             Command::none(),
         )
@@ -138,9 +410,20 @@ impl Application for TrashDoctor {
                 self.update_selection_stats();
             }
             Message::ShowDeleteConfirmation => {
-                if self.selected_count > 0 {
+                if self.would_remove_every_copy() {
+                    return Command::none();
+                }
+                
+                if self.selected_count > 0 && self.view_mode == ViewMode::Trash {
+                    self.state = AppState::ConfirmingDelete;
+                    self.message = format!("Permanently delete {} items from the trash ({})? They cannot be restored.", self.selected_count, format_file_size(self.total_size_selected));
+                    self.message_type = MessageType::Warning;
+                } else if self.selected_count > 0 && self.dry_run {
+                    let paths = self.selected_paths();
+                    self.review_plan(plan::plan_delete(&paths));
+                } else if self.selected_count > 0 {
                     self.state = AppState::ConfirmingDelete;
-                    self.message = format!("Are you sure you want to delete {} files? This action cannot be undone!", self.selected_count);
+                    self.message = format!("Permanently delete {} files? Deleted files cannot be restored; use Archive or Move to Trash to keep Undo available.", self.selected_count);
                     self.message_type = MessageType::Warning;
                 } else {
                     self.message = "No files selected for deletion.".to_string();
@@ -148,29 +431,11 @@ impl Application for TrashDoctor {
                 }
             }
             Message::ConfirmDelete => {
-                self.state = AppState::Processing;
-                let mut deleted_count = 0;
-                let mut failed_count = 0;
-                
-                for (i, selected) in self.selected.iter().enumerate() {
-                    if *selected && i < self.files.len() {
-                        match delete_file(&self.files[i].path) {
-                            Ok(_) => deleted_count += 1,
-                            Err(_) => failed_count += 1,
-                        }
-                    }
-                }
-                
-                self.state = AppState::Normal;
-                if failed_count == 0 {
-                    self.message = format!("Successfully deleted {} files.", deleted_count);
-                    self.message_type = MessageType::Success;
+                if self.view_mode == ViewMode::Trash {
+                    self.purge_selection();
                 } else {
-                    self.message = format!("Deleted {} files, failed to delete {} files.", deleted_count, failed_count);
-                    self.message_type = MessageType::Error;
+                    self.apply_to_selection("delete", "deleted", delete_files);
                 }
-                
-                return Command::perform(async {}, |_| Message::Refresh);
             }
             Message::CancelDelete => {
                 self.state = AppState::Normal;
@@ -184,53 +449,79 @@ impl Application for TrashDoctor {
                     return Command::none();
                 }
                 
-                self.state = AppState::Processing;
-                let mut archived_count = 0;
-                let mut failed_count = 0;
-                
-                for (i, selected) in self.selected.iter().enumerate() {
-                    if *selected && i < self.files.len() {
-                        match archive_file(&self.files[i].path) {
-                            Ok(_) => archived_count += 1,
-                            Err(_) => failed_count += 1,
-                        }
+                if !self.would_remove_every_copy() {
+                    let options = self.archive_options();
+                    if self.dry_run {
+                        // A plan lists the files inside the directories chosen
+                        let paths = files_under(&self.selected_paths());
+                        self.review_plan(plan::plan_archive(&paths, &options));
+                    } else if self.view_mode == ViewMode::Folders {
+                        self.confirm_folders(Operation::Archive);
+                    } else {
+                        self.apply_to_selection("archive", "archived", |paths| archive_files_with(paths, &options));
                     }
                 }
+            }
+            Message::TrashSelected => {
+                if self.selected_count == 0 {
+                    self.message = "No files selected for the trash.".to_string();
+                    self.message_type = MessageType::Warning;
+                    return Command::none();
+                }
                 
-                self.state = AppState::Normal;
-                if failed_count == 0 {
-                    self.message = format!("Successfully archived {} files.", archived_count);
-                    self.message_type = MessageType::Success;
-                } else {
-                    self.message = format!("Archived {} files, failed to archive {} files.", archived_count, failed_count);
-                    self.message_type = MessageType::Error;
+                if self.would_remove_every_copy() {
+                    return Command::none();
                 }
                 
-                return Command::perform(async {}, |_| Message::Refresh);
+                if self.dry_run {
+                    let paths = self.selected_paths();
+                    self.review_plan(plan::plan_trash(&paths));
+                } else if self.view_mode == ViewMode::Folders {
+                    self.confirm_folders(Operation::Trash);
+                } else {
+                    self.apply_to_selection("trash", "trashed", trash_files);
+                }
+            }
+            Message::ConfirmFolders => {
+                match self.state {
+                    AppState::ConfirmingFolders(Operation::Archive) => {
+                        let options = self.archive_options();
+                        self.apply_to_selection("archive", "archived", |paths| archive_paths_with(paths, &options));
+                    }
+                    AppState::ConfirmingFolders(Operation::Trash) => {
+                        self.apply_to_selection("trash", "trashed", trash_files);
+                    }
+                    _ => {}
+                }
+            }
+            Message::CancelFolders => {
+                self.state = AppState::Normal;
+                self.message = "Nothing was changed.".to_string();
+                self.message_type = MessageType::Info;
             }
             Message::FolderSelected(path) => {
                 if !path.is_empty() {
                     self.folder_path = path.clone();
-                    self.scan_and_filter();
+                    self.folder_view = path.clone();
+                    self.folder_selected.clear();
+                    self.watch_fallback = false;
+                    self.pending_changes.clear();
+                    self.scan_and_filter(true);
                 }
             }
             Message::ChangeAge(age) => {
                 self.age_filter = age;
-                if !self.folder_path.is_empty() {
-                    self.scan_and_filter();
-                }
+                self.sync_rule_filters();
+                self.apply_sort_and_filter();
             }
             Message::ChangeSize(size) => {
                 self.size_filter = size;
-                if !self.folder_path.is_empty() {
-                    self.scan_and_filter();
-                }
+                self.sync_rule_filters();
+                self.apply_sort_and_filter();
             }
             Message::Refresh => {
                 if !self.folder_path.is_empty() {
-                    self.scan_and_filter();
-                    self.message = "Files refreshed successfully.".to_string();
-                    self.message_type = MessageType::Success;
+                    self.scan_and_filter(true);
                 } else {
                     self.message = "No folder selected. Please select a folder first.".to_string();
                     self.message_type = MessageType::Warning;
@@ -261,8 +552,19 @@ impl Application for TrashDoctor {
                 self.message_type = MessageType::Info;
             }
             Message::PreviewFile(path) => {
-                self.message = format!("Preview: {}", path);
-                self.message_type = MessageType::Info;
+                self.show_details(&path);
+            }
+            Message::ChangeExplainPath(path) => {
+                self.explain_path = path;
+            }
+            Message::ExplainPath => {
+                let path = self.explain_path.trim().to_string();
+                if !path.is_empty() {
+                    self.show_details(&path);
+                }
+            }
+            Message::CloseDetails => {
+                self.details = None;
             }
             Message::ShowStats => {
                 let stats_text = format!(
@@ -281,21 +583,55 @@ impl Application for TrashDoctor {
             }
             Message::ToggleAutoRefresh(value) => {
                 self.auto_refresh = value;
+                self.watch_fallback = false;
                 if self.auto_refresh {
-                    self.message = "Auto-refresh enabled (every 30 seconds).".to_string();
-                    self.message_type = MessageType::Success;
-                    return Command::perform(
-                        async { tokio::time::sleep(Duration::from_secs(30)).await },
-                        |_| Message::AutoRefreshTick,
-                    );
+                    // The watcher reports back once it is running
+                    self.message = "Starting to watch for changes...".to_string();
+                    self.message_type = MessageType::Info;
                 } else {
                     self.message = "Auto-refresh disabled.".to_string();
                     self.message_type = MessageType::Info;
                 }
             }
             Message::AutoRefreshTick => {
-                if self.auto_refresh {
-                    self.scan_and_filter();
+                if self.auto_refresh && self.watch_fallback {
+                    // Leave a dry run under review alone
+                    if self.scan.is_none() && self.plan.is_none() {
+                        self.scan_and_filter(false);
+                    }
+                    return Command::perform(
+                        async { tokio::time::sleep(Duration::from_secs(30)).await },
+                        |_| Message::AutoRefreshTick,
+                    );
+                }
+            }
+            Message::WatchReady(folder) => {
+                if self.auto_refresh && folder == self.folder_path {
+                    self.message = "Watching for changes.".to_string();
+                    self.message_type = MessageType::Success;
+                    // Catch whatever changed before the watches were in place
+                    if self.scan.is_none() {
+                        self.scan_and_filter(false);
+                    }
+                }
+            }
+            Message::FilesChanged(folder, changes) => {
+                if self.auto_refresh && folder == self.folder_path {
+                    if changes.iter().any(|change| matches!(change, Change::Rescan)) {
+                        self.pending_changes.clear();
+                        self.scan_and_filter(false);
+                    } else if self.scan.is_some() {
+                        self.pending_changes.extend(changes);
+                    } else {
+                        self.apply_file_changes(changes);
+                    }
+                }
+            }
+            Message::WatchFailed(folder, error) => {
+                if self.auto_refresh && folder == self.folder_path && !self.watch_fallback {
+                    self.watch_fallback = true;
+                    self.message = format!("{}. Refreshing every 30 seconds instead.", error);
+                    self.message_type = MessageType::Warning;
                     return Command::perform(
                         async { tokio::time::sleep(Duration::from_secs(30)).await },
                         |_| Message::AutoRefreshTick,
@@ -305,187 +641,812 @@ impl Application for TrashDoctor {
             Message::DeleteSelected => {
                 return Command::perform(async {}, |_| Message::ShowDeleteConfirmation);
             }
-        }
-        Command::none()
-    }
-
-    fn view(&self) -> Element<Message> {
-        let header = text("TrashDoctor - Smart Disk Hygiene & File Management")
-            .size(24);
-
-        // Controls section
-        let controls = column![
-            row![
-                text("Folder:").width(Length::Fixed(60.0)),
-                button(" Select Folder").on_press(Message::SelectFolder),
-                text(&self.folder_path).width(Length::Fill),
-                button(" Refresh").on_press(Message::Refresh),
-                checkbox("Auto-refresh", self.auto_refresh, Message::ToggleAutoRefresh),
-            ]
-            .spacing(10)
-            .align_items(iced::Alignment::Center),
-
-            row![
-                text("Max Age (days):").width(Length::Fixed(120.0)),
-                text_input("30", &self.age_filter)
-                    .on_input(Message::ChangeAge)
-                    .width(Length::Fixed(80.0)),
-
-                text("Min Size (MB):").width(Length::Fixed(120.0)),
-                text_input("100", &self.size_filter)
-                    .on_input(Message::ChangeSize)
-                    .width(Length::Fixed(80.0)),
-
-                text("Sort by:").width(Length::Fixed(80.0)),
-                button("Date").on_press(Message::SortBy(SortCriteria::Date)),
-                button("Size").on_press(Message::SortBy(SortCriteria::Size)),
-                button("Name").on_press(Message::SortBy(SortCriteria::Name)),
-                button("Type").on_press(Message::SortBy(SortCriteria::Type)),
-            ]
-            .spacing(10)
-            .align_items(iced::Alignment::Center),
-
-            row![
-                text("Filter:").width(Length::Fixed(60.0)),
-                button("All").on_press(Message::FilterByType("All".to_string())),
-                button("Images").on_press(Message::FilterByType("Images".to_string())),
-                button("Documents").on_press(Message::FilterByType("Documents".to_string())),
-                button("Videos").on_press(Message::FilterByType("Videos".to_string())),
-                button("Show Stats").on_press(Message::ShowStats),
-                button("Export List").on_press(Message::ExportList),
-                button("Clear Message").on_press(Message::ClearMessage),
-            ]
-            .spacing(10)
-        ]
-        .spacing(15)
-        .padding(10);
-
-        // Selection controls
-        let selection_controls = row![
-            checkbox("Select All", self.selected.iter().all(|&x| x), Message::SelectAll),
-            text(format!("Selected: {} files ({:.2} MB)", 
-                self.selected_count, 
-                self.total_size_selected as f64 / (1024.0 * 1024.0)
-            )).width(Length::Fill),
-        ]
-        .spacing(10)
-        .align_items(iced::Alignment::Center)
-        .padding(10);
-
-        // File list header
-        let file_list_header = container(
-            row![
-                text("Select").width(Length::Fixed(60.0)),
-                text("File Path").width(Length::FillPortion(5)),
-                text("Size (KB)").width(Length::Fixed(100.0)),
-                text("Last Accessed").width(Length::Fixed(120.0)),
-                text("Actions").width(Length::Fixed(80.0)),
-            ]
-            .padding(5)
-        )
-        .style(theme::Container::Custom(Box::new(HeaderStyle)));
-
-        // File list rows
-        let file_list = self.files.iter().enumerate().fold(
-            column![file_list_header],
-            |col, (i, file)| {
-                let row_style = if i % 2 == 0 {
-                    theme::Container::Custom(Box::new(EvenRowStyle))
-                } else {
-                    theme::Container::Custom(Box::new(OddRowStyle))
+            Message::ScanProgressed(id, progress) => {
+                if let Some(job) = self.scan.as_mut().filter(|job| job.id == id) {
+                    job.progress = progress;
+                }
+            }
+            Message::ScanFinished(id, files) => {
+                // Results of a superseded scan are dropped
+                let Some(job) = self.scan.take_if(|job| job.id == id) else {
+                    return Command::none();
                 };
                 
-                col.push(
-                    container(
-                        row![
-                            checkbox("", self.selected.get(i).copied().unwrap_or(false), move |val| Message::ToggleSelection(i, val))
-                                .width(Length::Fixed(60.0)),
-                            text(&file.path).width(Length::FillPortion(5)),
-                            text(format!("{}", file.size / 1024)).width(Length::Fixed(100.0)),
-                            text(&file.last_accessed).width(Length::Fixed(120.0)),
-                            button("👁").on_press(Message::PreviewFile(file.path.clone())).width(Length::Fixed(80.0)),
-                        ]
-                        .padding(5)
-                        .spacing(5)
-                        .align_items(iced::Alignment::Center)
-                    )
-                    .style(row_style)
-                )
-            },
-        );
-
-        // Actions row
-        let actions = match self.state {
-            AppState::ConfirmingDelete => {
-                row![
-                    button("Confirm Delete").on_press(Message::ConfirmDelete),
-                    button("Cancel").on_press(Message::CancelDelete),
-                ]
-                .spacing(20)
-                .padding(10)
+                let cancelled = job.cancel.load(Ordering::Relaxed);
+                self.all_files = files;
+                apply_changes(&mut self.all_files, std::mem::take(&mut self.pending_changes));
+                self.dir_tree = None;
+                self.suggested_rules = suggest_rules_for_files(&self.all_files);
+                self.state = if self.plan.is_some() { AppState::ReviewingPlan } else { AppState::Normal };
+                self.apply_sort_and_filter();
+                self.update_stats();
+                
+                if cancelled {
+                    self.message = format!("Scan cancelled. Showing the {} files found so far.", self.all_files.len());
+                    self.message_type = MessageType::Warning;
+                } else if job.announce {
+                    self.message = format!("Scan complete: {} files found, {} match the filters.", self.all_files.len(), self.files.len());
+                    self.message_type = MessageType::Success;
+                }
             }
-            AppState::Processing => {
-                row![
-                    text("Processing..."),
-                    progress_bar(0.0..=100.0, 50.0),
-                ]
-                .spacing(20)
-                .padding(10)
+            Message::CancelScan => {
+                if let Some(job) = &self.scan {
+                    job.cancel.store(true, Ordering::Relaxed);
+                    self.message = "Cancelling scan...".to_string();
+                    self.message_type = MessageType::Info;
+                }
             }
-            AppState::Normal => {
-                row![
-                    button("Delete Selected").on_press(Message::DeleteSelected),
-                    button("Archive Selected").on_press(Message::ArchiveSelected),
-                ]
-                .spacing(20)
-                .padding(10)
+            Message::ShowView(mode) => {
+                match mode {
+                    ViewMode::Archive => self.load_archive(),
+                    ViewMode::Trash => self.load_trash(),
+                    _ => {}
+                }
+                self.view_mode = mode;
+                self.update_selection_stats();
             }
-        };
-
-        // Status message with color coding
-        let status_color = match self.message_type {
-            MessageType::Success => iced::Color::from_rgb(0.0, 0.7, 0.0),
-            MessageType::Error => iced::Color::from_rgb(0.8, 0.0, 0.0),
-            MessageType::Warning => iced::Color::from_rgb(0.8, 0.5, 0.0),
-            MessageType::Info => iced::Color::from_rgb(0.0, 0.0, 0.8),
-        };
-        
-        let status = text(&self.message).size(14).style(status_color);
-
-        // File count and size summary
-        let summary = text(format!(
-            "Total: {} files ({:.2} MB) | Filtered: {} files", 
-            self.all_files.len(),
-            self.all_files.iter().map(|f| f.size).sum::<u64>() as f64 / (1024.0 * 1024.0),
-            self.files.len()
-        )).size(12);
-
-        // Compose layout
-        column![
-            header,
-            controls,
-            selection_controls,
-            scrollable(file_list).height(Length::FillPortion(1)),
-            actions,
-            status,
-            summary,
+            Message::FindDuplicates => {
+                if self.all_files.is_empty() {
+                    self.message = "Scan a folder before looking for duplicates.".to_string();
+                    self.message_type = MessageType::Warning;
+                    return Command::none();
+                }
+                
+                self.finding_duplicates = true;
+                self.message = "Comparing file contents...".to_string();
+                self.message_type = MessageType::Info;
+                
+                let files = self.all_files.clone();
+                return Command::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || get_duplicate_files(&files))
+                            .await
+                            .unwrap_or_default()
+                    },
+                    Message::DuplicatesFound,
+                );
+            }
+            Message::DuplicatesFound(groups) => {
+                self.finding_duplicates = false;
+                let reclaimable: u64 = groups.iter().map(|g| g.reclaimable_bytes()).sum();
+                self.message = format!("Found {} duplicate groups, {} reclaimable.", groups.len(), format_file_size(reclaimable));
+                self.message_type = MessageType::Success;
+                self.set_duplicates(groups);
+            }
+            Message::ToggleDuplicate(group, index, value) => {
+                if let Some(selected) = self.duplicate_selected.get_mut(group).and_then(|g| g.get_mut(index)) {
+                    *selected = value;
+                    self.update_selection_stats();
+                }
+            }
+            Message::ApplyKeepPolicy(policy) => {
+                if policy == KeepPolicy::PreferDirectory(String::new()) {
+                    self.message = "Enter a preferred folder first.".to_string();
+                    self.message_type = MessageType::Warning;
+                    return Command::none();
+                }
+                
+                for (group, selected) in self.duplicates.iter().zip(self.duplicate_selected.iter_mut()) {
+                    let keeper = group.keeper(&policy);
+                    *selected = (0..group.files.len()).map(|i| i != keeper).collect();
+                }
+                self.update_selection_stats();
+            }
+            Message::ChangePreferredDir(dir) => {
+                self.preferred_dir = dir;
+            }
+            Message::DedupeSelected => {
+                if self.selected_count == 0 {
+                    self.message = "No duplicate copies selected.".to_string();
+                    self.message_type = MessageType::Warning;
+                    return Command::none();
+                }
+                
+                if self.would_remove_every_copy() {
+                    return Command::none();
+                }
+                
+                if self.dry_run {
+                    let (pairs, _) = self.dedupe_pairs();
+                    self.review_plan(plan::plan_link(&pairs));
+                } else {
+                    self.dedupe_selection();
+                }
+            }
+            Message::UndoLast => {
+                let undone = undo_last_batch();
+                self.undo_blocked = undone.as_ref().is_ok_and(|report| report.blocked > 0);
+                match undone {
+                    Ok(report) if report.operation.is_none() => {
+                        self.message = "Nothing to undo.".to_string();
+                        self.message_type = MessageType::Info;
+                    }
+                    Ok(report) if report.skipped.is_empty() => {
+                        self.message = format!("Restored {} files.", report.restored.len());
+                        self.message_type = MessageType::Success;
+                    }
+                    Ok(report) => {
+                        self.message = format!(
+                            "Restored {} files; {} could not be restored ({}).",
+                            report.restored.len(),
+                            report.skipped.len(),
+                            report.skipped[0].1
+                        );
+                        if report.blocked > 0 {
+                            self.message.push_str(" Undo again once they are free, or Skip Blocked to move on to the batch before.");
+                        }
+                        self.message_type = MessageType::Warning;
+                    }
+                    Err(err) => {
+                        self.message = format!("Undo failed: {}", err);
+                        self.message_type = MessageType::Error;
+                    }
+                }
+                self.load_archive();
+                self.load_trash();
+                self.scan_and_filter(false);
+            }
+            Message::SkipUndo => {
+                self.undo_blocked = false;
+                match skip_last_batch() {
+                    Ok(entries) => {
+                        self.message = format!("Gave up on {} files; Undo Last now moves on to the batch before.", entries.len());
+                        self.message_type = MessageType::Info;
+                    }
+                    Err(err) => {
+                        self.message = format!("Could not update the journal: {}", err);
+                        self.message_type = MessageType::Error;
+                    }
+                }
+            }
+            Message::ToggleArchived(index, value) => {
+                if let Some(selected) = self.archive_selected.get_mut(index) {
+                    *selected = value;
+                    self.update_selection_stats();
+                }
+            }
+            Message::OpenFolder(path) => {
+                self.folder_view = path;
+            }
+            Message::ToggleFolderEntry(path, value) => {
+                self.folder_selected.retain(|selected| *selected != path);
+                if value {
+                    self.folder_selected.push(path);
+                }
+                self.update_selection_stats();
+            }
+            Message::RestoreSelected => {
+                self.restore_selection(None);
+            }
+            Message::RestoreSelectedTo => {
+                if self.selected_count == 0 {
+                    self.message = "Nothing selected to restore.".to_string();
+                    self.message_type = MessageType::Warning;
+                    return Command::none();
+                }
+                return Command::perform(
+                    async move {
+                        FileDialog::new()
+                            .pick_folder()
+                            .map(|folder| folder.display().to_string())
+                            .unwrap_or_default()
+                    },
+                    Message::RestoreTargetSelected,
+                );
+            }
+            Message::RestoreTargetSelected(path) => {
+                if !path.is_empty() {
+                    self.restore_selection(Some(std::path::Path::new(&path)));
+                }
+            }
+            Message::ChangeCompression(level) => {
+                self.compression_level = level;
+            }
+            Message::ChangeArchiveDestination(dir) => {
+                self.archive_destination = dir;
+            }
+            Message::ToggleTrashed(index, value) => {
+                if let Some(selected) = self.trash_selected.get_mut(index) {
+                    *selected = value;
+                    self.update_selection_stats();
+                }
+            }
+            Message::ChangePurgeAge(days) => {
+                self.purge_age = days;
+            }
+            Message::SelectOlderThan => {
+                let Ok(days) = self.purge_age.trim().parse() else {
+                    self.message = "Enter the age in whole days.".to_string();
+                    self.message_type = MessageType::Warning;
+                    return Command::none();
+                };
+                let now = chrono::Local::now().naive_local();
+                let picked: Vec<TrashEntry> = trashcan::older_than(&self.trash_entries, days, now)
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                self.select_trash_entries(&picked);
+            }
+            Message::ChangeTrashCap(mb) => {
+                self.trash_cap = mb;
+            }
+            Message::SelectOverCap => {
+                let Ok(mb) = self.trash_cap.trim().parse::<u64>() else {
+                    self.message = "Enter the size limit in whole megabytes.".to_string();
+                    self.message_type = MessageType::Warning;
+                    return Command::none();
+                };
+                let Some(max_bytes) = mb_to_bytes(mb) else {
+                    self.message = format!("A size limit of {} MB is too large.", mb);
+                    self.message_type = MessageType::Warning;
+                    return Command::none();
+                };
+                let picked: Vec<TrashEntry> = trashcan::over_size_cap(&self.trash_entries, max_bytes)
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                self.select_trash_entries(&picked);
+            }
+            Message::SelectArchiveDestination => {
+                let current = self.archive_destination.clone();
+                return Command::perform(
+                    async move {
+                        FileDialog::new()
+                            .pick_folder()
+                            .map(|folder| folder.display().to_string())
+                            .unwrap_or(current)
+                    },
+                    Message::ChangeArchiveDestination,
+                );
+            }
+            Message::ToggleDryRun(value) => {
+                self.dry_run = value;
+            }
+            Message::ExecutePlan => {
+                if let Some(plan) = self.plan.take() {
+                    self.execute_plan(&plan);
+                }
+            }
+            Message::ExportPlan => {
+                return Command::perform(
+                    async move {
+                        FileDialog::new()
+                            .set_file_name("trashdoctor-plan.json")
+                            .add_filter("Plan", &["json"])
+                            .save_file()
+                            .map(|file| file.display().to_string())
+                            .unwrap_or_default()
+                    },
+                    Message::PlanExportTarget,
+                );
+            }
+            Message::PlanExportTarget(path) => {
+                if let (Some(plan), false) = (&self.plan, path.is_empty()) {
+                    match plan.save(std::path::Path::new(&path)) {
+                        Ok(()) => {
+                            self.message = format!("Plan saved to {}; apply it later with 'trashdoctor apply'.", path);
+                            self.message_type = MessageType::Success;
+                        }
+                        Err(err) => {
+                            self.message = format!("Could not save the plan: {}", err);
+                            self.message_type = MessageType::Error;
+                        }
+                    }
+                }
+            }
+            Message::UseRule(source, index) => {
+                let rules = match source {
+                    RuleSource::Predefined => &self.predefined_rules,
+                    RuleSource::Saved => &self.saved_rules,
+                    RuleSource::Suggested => &self.suggested_rules,
+                };
+                if let Some(rule) = rules.get(index).cloned() {
+                    self.rule_form = RuleForm::from_rule(&rule.name, &rule.config);
+                    self.use_rule(rule.config);
+                    self.message = format!("Using rule '{}': {} files match.", rule.name, self.files.len());
+                    self.message_type = MessageType::Info;
+                }
+            }
+            Message::ToggleRuleEditor => {
+                self.show_rule_editor = !self.show_rule_editor;
+                if self.show_rule_editor {
+                    // Start from what is applied now, including the age and size boxes
+                    self.sync_rule_filters();
+                    self.rule_form = RuleForm::from_rule(&self.rule_form.name, &self.rule);
+                }
+            }
+            Message::EditRule(field, value) => {
+                let form = &mut self.rule_form;
+                let target = match field {
+                    RuleField::Name => &mut form.name,
+                    RuleField::MaxAge => &mut form.max_age,
+                    RuleField::MinSize => &mut form.min_size,
+                    RuleField::MaxSize => &mut form.max_size,
+                    RuleField::Types => &mut form.types,
+                    RuleField::ExcludeTypes => &mut form.exclude_types,
+                    RuleField::Patterns => &mut form.patterns,
+                    RuleField::ExcludePatterns => &mut form.exclude_patterns,
+                    RuleField::ArchiveTo => &mut form.archive_to,
+                    RuleField::Expression => &mut form.expression,
+                };
+                *target = value;
+            }
+            Message::ToggleRuleFlag(flag, value) => {
+                match flag {
+                    RuleFlag::Hidden => self.rule_form.include_hidden = value,
+                    RuleFlag::Readonly => self.rule_form.include_readonly = value,
+                    RuleFlag::Executable => self.rule_form.include_executable = value,
+                }
+            }
+            Message::ChangeAgeBasis(basis) => {
+                self.rule_form.age_basis = basis;
+            }
+            Message::UseModifiedAge => {
+                self.rule.age_basis = AgeBasis::Modify;
+                self.rule_form.age_basis = AgeBasis::Modify;
+                self.apply_sort_and_filter();
+                self.message = format!("Ages now count from the last modification: {} files match.", self.files.len());
+                self.message_type = MessageType::Info;
+            }
+            Message::ApplyRuleForm => {
+                match self.rule_form.to_config() {
+                    Ok(config) => {
+                        self.use_rule(config);
+                        self.message = format!("Rule applied: {} files match.", self.files.len());
+                        self.message_type = MessageType::Info;
+                    }
+                    Err(err) => {
+                        self.message = err;
+                        self.message_type = MessageType::Warning;
+                    }
+                }
+            }
+            Message::SaveRule => {
+                let name = self.rule_form.name.trim().to_string();
+                if name.is_empty() {
+                    self.message = "Enter a name for the rule first.".to_string();
+                    self.message_type = MessageType::Warning;
+                    return Command::none();
+                }
+                let config = match self.rule_form.to_config() {
+                    Ok(config) => config,
+                    Err(err) => {
+                        self.message = err;
+                        self.message_type = MessageType::Warning;
+                        return Command::none();
+                    }
+                };
+                
+                // Keep the description of a rule being edited
+                let description = self.saved_rules.iter()
+                    .chain(&self.predefined_rules)
+                    .chain(&self.suggested_rules)
+                    .find(|rule| rule.name.eq_ignore_ascii_case(&name))
+                    .map(|rule| rule.description.clone())
+                    .unwrap_or_else(|| "Saved from TrashDoctor".to_string());
+                match save_rule(&SmartRule::new(&name, &description, config)) {
+                    Ok(path) => {
+                        self.saved_rules = load_saved_rules().0;
+                        self.message = format!("Saved rule '{}' to {}.", name, path.display());
+                        self.message_type = MessageType::Success;
+                    }
+                    Err(err) => {
+                        self.message = format!("Could not save the rule: {}", err);
+                        self.message_type = MessageType::Error;
+                    }
+                }
+            }
+            Message::DeleteRule => {
+                let name = self.rule_form.name.trim().to_string();
+                match delete_saved_rule(&name) {
+                    Ok(()) => {
+                        self.saved_rules = load_saved_rules().0;
+                        self.message = format!("Deleted saved rule '{}'.", name);
+                        self.message_type = MessageType::Success;
+                    }
+                    Err(FileActionError::FileNotFound) => {
+                        self.message = format!("There is no saved rule named '{}'.", name);
+                        self.message_type = MessageType::Warning;
+                    }
+                    Err(err) => {
+                        self.message = format!("Could not delete the rule: {}", err);
+                        self.message_type = MessageType::Error;
+                    }
+                }
+            }
+            Message::DiscardPlan => {
+                self.plan = None;
+                self.state = AppState::Normal;
+                self.message = "Dry run discarded; nothing was changed.".to_string();
+                self.message_type = MessageType::Info;
+            }
+        }
+        Command::none()
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        let scan = match &self.scan {
+            Some(job) => scan_subscription(
+                job.id,
+                self.folder_path.clone(),
+                // The list is sorted for display anyway
+                ScanOptions { ordered: false, ..ScanOptions::default() },
+                job.cancel.clone(),
+            ),
+            None => Subscription::none(),
+        };
+        let watch = match self.auto_refresh && !self.watch_fallback && !self.folder_path.is_empty() {
+            true => watch_subscription(self.folder_path.clone(), ScanOptions::default()),
+            false => Subscription::none(),
+        };
+        Subscription::batch([scan, watch])
+    }
+
+    fn view(&self) -> Element<Message> {
+        let header = text("TrashDoctor - Smart Disk Hygiene & File Management")
+            .size(24);
+
+        // Where bundles go when no destination is given
+        let default_destination = format!("{} (default)", archive::bundles_dir().display());
+
+        // Controls section
+        let controls = column![
+            row![
+                text("Folder:").width(Length::Fixed(60.0)),
+                button(" Select Folder").on_press(Message::SelectFolder),
+                text(&self.folder_path).width(Length::Fill),
+                button(" Refresh").on_press(Message::Refresh),
+                checkbox("Auto-refresh", self.auto_refresh, Message::ToggleAutoRefresh),
+            ]
+            .spacing(10)
+            .align_items(iced::Alignment::Center),
+
+            row![
+                text("Max Age (days):").width(Length::Fixed(120.0)),
+                text_input("30", &self.age_filter)
+                    .on_input(Message::ChangeAge)
+                    .width(Length::Fixed(80.0)),
+
+                text("Min Size (MB):").width(Length::Fixed(120.0)),
+                text_input("100", &self.size_filter)
+                    .on_input(Message::ChangeSize)
+                    .width(Length::Fixed(80.0)),
+
+                text("Sort by:").width(Length::Fixed(80.0)),
+                button("Date").on_press(Message::SortBy(SortCriteria::Date)),
+                button("Size").on_press(Message::SortBy(SortCriteria::Size)),
+                button("Name").on_press(Message::SortBy(SortCriteria::Name)),
+                button("Type").on_press(Message::SortBy(SortCriteria::Type)),
+            ]
+            .spacing(10)
+            .align_items(iced::Alignment::Center),
+
+            row![
+                text("Filter:").width(Length::Fixed(60.0)),
+                button("All").on_press(Message::FilterByType("All".to_string())),
+                button("Images").on_press(Message::FilterByType("Images".to_string())),
+                button("Documents").on_press(Message::FilterByType("Documents".to_string())),
+                button("Videos").on_press(Message::FilterByType("Videos".to_string())),
+                button("Show Stats").on_press(Message::ShowStats),
+                button("Export List").on_press(Message::ExportList),
+                button("Clear Message").on_press(Message::ClearMessage),
+            ]
+            .spacing(10),
+
+            row![
+                text("Archive to:").width(Length::Fixed(80.0)),
+                text_input(&default_destination, &self.archive_destination)
+                    .on_input(Message::ChangeArchiveDestination)
+                    .width(Length::Fill),
+                button("Browse").on_press(Message::SelectArchiveDestination),
+                text("Compression:"),
+                pick_list(&COMPRESSION_LEVELS[..], Some(self.compression_level), Message::ChangeCompression),
+            ]
+            .spacing(10)
+            .align_items(iced::Alignment::Center)
         ]
         .spacing(15)
-        .padding(15)
-        .into()
+        .padding(10);
+
+        let tabs = row![
+            button("Files").on_press(Message::ShowView(ViewMode::Files)),
+            button("Folders").on_press(Message::ShowView(ViewMode::Folders)),
+            button("Duplicates").on_press(Message::ShowView(ViewMode::Duplicates)),
+            button("Archive").on_press(Message::ShowView(ViewMode::Archive)),
+            button("Trash").on_press(Message::ShowView(ViewMode::Trash)),
+            button(if self.show_rule_editor { "Hide Rule Editor" } else { "Edit Rule" }).on_press(Message::ToggleRuleEditor),
+        ]
+        .spacing(10)
+        .padding([0, 10]);
+
+        // Selection controls
+        let selection_summary = text(format!("Selected: {} files ({:.2} MB)", 
+            self.selected_count, 
+            self.total_size_selected as f64 / (1024.0 * 1024.0)
+        )).width(Length::Fill);
+        let selection_controls = match self.view_mode {
+            ViewMode::Files => row![
+                checkbox("Select All", self.selected.iter().all(|&x| x), Message::SelectAll),
+                selection_summary,
+                text_input("Why isn't a file listed? Enter its path", &self.explain_path)
+                    .on_input(Message::ChangeExplainPath)
+                    .on_submit(Message::ExplainPath)
+                    .width(Length::Fixed(320.0)),
+                button("Explain").on_press(Message::ExplainPath),
+            ],
+            ViewMode::Folders | ViewMode::Duplicates | ViewMode::Archive | ViewMode::Trash => row![selection_summary],
+        }
+        .spacing(10)
+        .align_items(iced::Alignment::Center)
+        .padding(10);
+
+        // File list header
+        let file_list_header = container(
+            row![
+                text("Select").width(Length::Fixed(60.0)),
+                text("File Path").width(Length::FillPortion(5)),
+                text("Size (KB)").width(Length::Fixed(100.0)),
+                text("Last Accessed").width(Length::Fixed(120.0)),
+                text("Actions").width(Length::Fixed(80.0)),
+            ]
+            .padding(5)
+        )
+        .style(theme::Container::Custom(Box::new(HeaderStyle)));
+
+        // File list rows
+        let file_list = self.files.iter().enumerate().fold(
+            column![file_list_header],
+            |col, (i, file)| {
+                let row_style = if i % 2 == 0 {
+                    theme::Container::Custom(Box::new(EvenRowStyle))
+                } else {
+                    theme::Container::Custom(Box::new(OddRowStyle))
+                };
+                
+                col.push(
+                    container(
+                        row![
+                            checkbox("", self.selected.get(i).copied().unwrap_or(false), move |val| Message::ToggleSelection(i, val))
+                                .width(Length::Fixed(60.0)),
+                            text(&file.path).width(Length::FillPortion(5)),
+                            text(format!("{}", file.size / 1024)).width(Length::Fixed(100.0)),
+                            text(&file.last_accessed).width(Length::Fixed(120.0)),
+                            button("👁").on_press(Message::PreviewFile(file.path.clone())).width(Length::Fixed(80.0)),
+                        ]
+                        .padding(5)
+                        .spacing(5)
+                        .align_items(iced::Alignment::Center)
+                    )
+                    .style(row_style)
+                )
+            },
+        );
+
+        // Actions row
+        let actions = match self.state {
+            AppState::ConfirmingDelete => {
+                row![
+                    button("Confirm Delete").on_press(Message::ConfirmDelete),
+                    button("Cancel").on_press(Message::CancelDelete),
+                ]
+                .spacing(20)
+                .padding(10)
+            }
+            AppState::ConfirmingFolders(operation) => {
+                let label = if operation == Operation::Archive { "Confirm Archive" } else { "Confirm Move to Trash" };
+                row![
+                    button(label).on_press(Message::ConfirmFolders),
+                    button("Cancel").on_press(Message::CancelFolders),
+                ]
+                .spacing(20)
+                .padding(10)
+            }
+            AppState::ReviewingPlan => {
+                row![
+                    button("Execute Plan").on_press(Message::ExecutePlan),
+                    button("Export Plan...").on_press(Message::ExportPlan),
+                    button("Discard").on_press(Message::DiscardPlan),
+                ]
+                .spacing(20)
+                .padding(10)
+            }
+            AppState::Processing => {
+                row![
+                    text("Processing..."),
+                    progress_bar(0.0..=100.0, 50.0),
+                ]
+                .spacing(20)
+                .padding(10)
+            }
+            AppState::Scanning => {
+                let progress = self.scan.as_ref().map(|job| job.progress.clone()).unwrap_or_default();
+                row![
+                    text(format!(
+                        "Scanning... {} files ({})",
+                        progress.files_scanned,
+                        format_file_size(progress.bytes_seen)
+                    )),
+                    progress_bar(0.0..=1.0, progress.fraction()).width(Length::Fixed(200.0)),
+                    text(&progress.current_path).size(12).width(Length::Fill),
+                    button("Cancel").on_press(Message::CancelScan),
+                ]
+                .spacing(20)
+                .padding(10)
+                .align_items(iced::Alignment::Center)
+            }
+            AppState::Normal if self.view_mode == ViewMode::Archive => {
+                let mut actions = row![
+                    button("Restore to Original Location").on_press(Message::RestoreSelected),
+                    button("Restore To...").on_press(Message::RestoreSelectedTo),
+                    button("Undo Last").on_press(Message::UndoLast),
+                ];
+                if self.undo_blocked {
+                    actions = actions.push(button("Skip Blocked").on_press(Message::SkipUndo));
+                }
+                actions
+                    .spacing(20)
+                    .padding(10)
+            }
+            AppState::Normal if self.view_mode == ViewMode::Trash => {
+                row![
+                    button("Restore to Original Location").on_press(Message::RestoreSelected),
+                    button("Restore To...").on_press(Message::RestoreSelectedTo),
+                    button("Delete Permanently").on_press(Message::DeleteSelected),
+                ]
+                .spacing(20)
+                .padding(10)
+            }
+            AppState::Normal => {
+                // Directories can be archived or trashed, not deleted outright
+                let mut actions = match self.view_mode {
+                    ViewMode::Folders => row![],
+                    _ => row![button("Delete Selected").on_press(Message::DeleteSelected)],
+                };
+                actions = actions
+                    .push(button("Archive Selected").on_press(Message::ArchiveSelected))
+                    .push(button("Move to Trash").on_press(Message::TrashSelected));
+                if self.view_mode == ViewMode::Duplicates {
+                    actions = actions.push(button("Deduplicate in Place").on_press(Message::DedupeSelected));
+                }
+                actions = actions.push(button("Undo Last").on_press(Message::UndoLast));
+                if self.undo_blocked {
+                    actions = actions.push(button("Skip Blocked").on_press(Message::SkipUndo));
+                }
+                actions = actions.push(checkbox("Dry run", self.dry_run, Message::ToggleDryRun));
+                actions
+                    .align_items(iced::Alignment::Center)
+                    .spacing(20)
+                    .padding(10)
+            }
+        };
+
+        // Status message with color coding
+        let status_color = match self.message_type {
+            MessageType::Success => iced::Color::from_rgb(0.0, 0.7, 0.0),
+            MessageType::Error => iced::Color::from_rgb(0.8, 0.0, 0.0),
+            MessageType::Warning => iced::Color::from_rgb(0.8, 0.5, 0.0),
+            MessageType::Info => iced::Color::from_rgb(0.0, 0.0, 0.8),
+        };
+        
+        let status = text(&self.message).size(14).style(status_color);
+
+        // File count and size summary
+        let summary = text(format!(
+            "Total: {} files ({:.2} MB) | Filtered: {} files", 
+            self.all_files.len(),
+            self.all_files.iter().map(|f| f.size).sum::<u64>() as f64 / (1024.0 * 1024.0),
+            self.files.len()
+        )).size(12);
+
+        let body: Element<Message> = match (&self.plan, &self.view_mode) {
+            (Some(plan), _) => self.view_plan(plan),
+            (None, ViewMode::Files) => scrollable(file_list).height(Length::FillPortion(1)).into(),
+            (None, ViewMode::Folders) => self.view_folders(),
+            (None, ViewMode::Duplicates) => self.view_duplicates(),
+            (None, ViewMode::Archive) => self.view_archive(),
+            (None, ViewMode::Trash) => self.view_trash(),
+        };
+
+        let body: Element<Message> = match &self.details {
+            Some(details) => column![body, self.view_details(details)].spacing(10).into(),
+            None => body,
+        };
+
+        let body = row![self.view_rule_sidebar(), body]
+            .spacing(10)
+            .height(Length::FillPortion(1));
+
+        let mut layout = column![header, controls, tabs]
+            .spacing(15)
+            .padding(15);
+        if self.show_rule_editor {
+            layout = layout.push(self.view_rule_editor());
+        }
+
+        if let Some(warning) = &self.atime_warning {
+            layout = layout.push(
+                row![
+                    text(format!("⚠ {}.", warning))
+                        .size(14)
+                        .style(iced::Color::from_rgb(0.8, 0.5, 0.0))
+                        .width(Length::Fill),
+                    button("Use Modification Time").on_press(Message::UseModifiedAge),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center)
+            );
+        }
+
+        // Compose layout
+        layout
+            .push(selection_controls)
+            .push(body)
+            .push(actions)
+            .push(status)
+            .push(summary)
+            .into()
     }
 }
 
 impl TrashDoctor {
-    fn scan_and_filter(&mut self) {
-        self.all_files = scan_folder(&self.folder_path);
+    // Starts a background scan; results arrive as Message::ScanFinished
+    fn scan_and_filter(&mut self, announce: bool) {
+        if let Some(job) = self.scan.take() {
+            job.cancel.store(true, Ordering::Relaxed);
+        }
+        
+        self.sync_rule_filters();
+        
+        self.next_scan_id += 1;
+        self.scan = Some(ScanJob {
+            id: self.next_scan_id,
+            cancel: Arc::new(AtomicBool::new(false)),
+            progress: ScanProgress::default(),
+            announce,
+        });
+        self.state = AppState::Scanning;
+    }
+
+    // Opens the detail pane for a scanned file, or one read from disk
+    fn show_details(&mut self, path: &str) {
+        let file = match self.all_files.iter().find(|f| f.path == path) {
+            Some(file) => file.clone(),
+            None => match file_info(std::path::Path::new(path)) {
+                Ok(file) => file,
+                Err(err) => {
+                    self.message = format!("Cannot read {}: {}", path, err);
+                    self.message_type = MessageType::Error;
+                    return;
+                }
+            },
+        };
+        
+        self.sync_rule_filters();
+        let scan_options = ScanOptions::default();
+        self.details = Some(FileDetails {
+            explanation: explain(&file, &self.rule),
+            skipped_by_scan: skip_reason(std::path::Path::new(path), &scan_options),
+            file,
+        });
+    }
+
+    // Makes a rule current, mirroring its age and size in the filter boxes
+    fn use_rule(&mut self, config: RuleConfig) {
+        self.age_filter = config.max_age_days.to_string();
+        self.size_filter = config.min_size_mb.to_string();
+        self.rule = config;
+        self.apply_sort_and_filter();
+    }
+
+    // Copies the age and size boxes into the rule
+    fn sync_rule_filters(&mut self) {
         self.rule.max_age_days = self.age_filter.parse().unwrap_or(30);
         self.rule.min_size_mb = self.size_filter.parse().unwrap_or(100);
-        self.apply_sort_and_filter();
-        self.update_stats();
     }
 
     fn apply_sort_and_filter(&mut self) {
+        self.atime_warning = atime_warning(&self.all_files, &self.rule);
+        self.update_dir_tree();
         let mut filtered = apply_rules(&self.all_files, &self.rule);
         
         // Apply file type filtering
@@ -548,12 +1509,954 @@ impl TrashDoctor {
     }
 
     fn update_selection_stats(&mut self) {
-        self.selected_count = self.selected.iter().filter(|&&x| x).count();
-        self.total_size_selected = self.selected.iter().enumerate()
+        if self.view_mode == ViewMode::Archive {
+            let selected: Vec<&ArchiveEntry> = self.selected_archive_entries();
+            self.total_size_selected = selected.iter().map(|e| e.size).sum();
+            self.selected_count = selected.len();
+            return;
+        }
+        if self.view_mode == ViewMode::Trash {
+            let selected: Vec<&TrashEntry> = self.selected_trash_entries();
+            self.total_size_selected = selected.iter().map(|e| e.size).sum();
+            self.selected_count = selected.len();
+            return;
+        }
+        
+        // Ticked directories go whole, so count what is on disk under them
+        let (count, size) = match self.view_mode {
+            ViewMode::Folders => disk_usage(&self.folder_selected),
+            _ => {
+                let selected = self.selected_files();
+                (selected.len(), selected.iter().map(|f| f.size).sum())
+            }
+        };
+        self.selected_count = count;
+        self.total_size_selected = size;
+    }
+
+    fn archive_options(&self) -> ArchiveOptions {
+        let destination = Some(self.archive_destination.trim())
+            .filter(|dir| !dir.is_empty())
+            .map(std::path::PathBuf::from);
+        ArchiveOptions { compression_level: self.compression_level, destination }
+            .with_rule(&self.rule)
+    }
+
+    // Asks before archiving or trashing ticked directories, with what is
+    // really on disk under them next to what the scan listed
+    fn confirm_folders(&mut self, operation: Operation) {
+        let listed = self.selected_files();
+        let (listed_count, listed_size) = (listed.len(), listed.iter().map(|f| f.size).sum::<u64>());
+        let verb = if operation == Operation::Archive { "Archive" } else { "Move to the trash" };
+        self.message = format!("{} {} files ({}) on disk?", verb, self.selected_count, format_file_size(self.total_size_selected));
+        if self.selected_count > listed_count {
+            self.message.push_str(&format!(
+                " The scan listed {} of them ({}); the rest are hidden files, .git, node_modules and others it skips.",
+                listed_count,
+                format_file_size(listed_size)
+            ));
+        }
+        self.message_type = MessageType::Warning;
+        self.state = AppState::ConfirmingFolders(operation);
+    }
+
+    fn selected_archive_entries(&self) -> Vec<&ArchiveEntry> {
+        self.archive_entries.iter()
+            .zip(&self.archive_selected)
+            .filter(|(_, &selected)| selected)
+            .map(|(entry, _)| entry)
+            .collect()
+    }
+
+    fn selected_trash_entries(&self) -> Vec<&TrashEntry> {
+        self.trash_entries.iter()
+            .zip(&self.trash_selected)
             .filter(|(_, &selected)| selected)
-            .map(|(i, _)| self.files.get(i).map(|f| f.size).unwrap_or(0))
-            .sum();
+            .map(|(entry, _)| entry)
+            .collect()
+    }
+
+    // Ticks exactly the given trash entries
+    fn select_trash_entries(&mut self, picked: &[TrashEntry]) {
+        self.trash_selected = self.trash_entries.iter()
+            .map(|entry| picked.iter().any(|p| p.trashed_path == entry.trashed_path))
+            .collect();
+        self.update_selection_stats();
+        self.message = format!("Selected {} items ({}).", self.selected_count, format_file_size(self.total_size_selected));
+        self.message_type = MessageType::Info;
+    }
+
+    // Files ticked in whichever view is showing
+    fn selected_files(&self) -> Vec<&FileInfo> {
+        match self.view_mode {
+            ViewMode::Files => self.files.iter()
+                .zip(&self.selected)
+                .filter(|(_, &selected)| selected)
+                .map(|(file, _)| file)
+                .collect(),
+            ViewMode::Duplicates => self.duplicates.iter()
+                .zip(&self.duplicate_selected)
+                .flat_map(|(group, selected)| {
+                    group.files.iter()
+                        .zip(selected)
+                        .filter(|(_, &selected)| selected)
+                        .map(|(file, _)| file)
+                })
+                .collect(),
+            ViewMode::Folders => self.all_files.iter()
+                .filter(|file| {
+                    std::path::Path::new(&file.path)
+                        .ancestors()
+                        .any(|path| self.folder_selected.iter().any(|selected| std::path::Path::new(selected) == path))
+                })
+                .collect(),
+            ViewMode::Archive | ViewMode::Trash => Vec::new(),
+        }
+    }
+
+    // Guards against selecting every copy of a duplicate group
+    fn would_remove_every_copy(&mut self) -> bool {
+        if self.view_mode != ViewMode::Duplicates {
+            return false;
+        }
+        
+        let all_selected = self.duplicate_selected.iter()
+            .filter(|selected| selected.iter().all(|&s| s))
+            .count();
+        if all_selected > 0 {
+            self.message = format!("Every copy is selected in {} duplicate groups; leave at least one copy unselected.", all_selected);
+            self.message_type = MessageType::Warning;
+            return true;
+        }
+        
+        false
+    }
+
+    fn apply_to_selection(&mut self, verb: &str, past: &str, action: impl FnOnce(&[String]) -> BatchOutcome) {
+        self.state = AppState::Processing;
+        let outcome = if self.view_mode == ViewMode::Duplicates {
+            // Only copies still identical to the one kept are touched
+            let (pairs, _) = self.dedupe_pairs();
+            let (same, different): (Vec<_>, Vec<_>) = verify_duplicates(&pairs)
+                .into_iter()
+                .partition(|(_, result)| result.is_ok());
+            let paths: Vec<String> = same.into_iter().map(|(path, _)| path).collect();
+            let mut outcome = match paths.is_empty() {
+                true => BatchOutcome::default(),
+                false => action(&paths),
+            };
+            outcome.results.extend(different);
+            outcome
+        } else {
+            action(&self.selected_paths())
+        };
+        self.finish_batch(verb, past, outcome);
+    }
+
+    fn finish_batch(&mut self, verb: &str, past: &str, outcome: BatchOutcome) {
+        let done = outcome.succeeded();
+        let failed_count = outcome.failed_count();
+        
+        self.state = AppState::Normal;
+        if failed_count == 0 {
+            self.message = format!("Successfully {} {} files.", past, done.len());
+            self.message_type = MessageType::Success;
+        } else {
+            self.message = format!("{} {} files, failed to {} {} files.", capitalize(past), done.len(), verb, failed_count);
+            self.message_type = MessageType::Error;
+        }
+        
+        self.forget_duplicates(&done);
+        self.forget_files(&done);
+    }
+
+    // Whole directories stay as they are, for the actions that take them
+    fn selected_paths(&self) -> Vec<String> {
+        match self.view_mode {
+            ViewMode::Folders => self.folder_selected.clone(),
+            _ => self.selected_files().iter().map(|f| f.path.clone()).collect(),
+        }
+    }
+
+    // Shows a dry run for review instead of acting on the selection
+    fn review_plan(&mut self, mut plan: Plan) {
+        if self.view_mode == ViewMode::Duplicates {
+            let (pairs, _) = self.dedupe_pairs();
+            let differ = verify_duplicates(&pairs)
+                .into_iter()
+                .filter(|(_, result)| matches!(result, Err(FileActionError::ContentMismatch)));
+            for (path, _) in differ {
+                if let Some(step) = plan.steps.iter_mut().find(|step| step.path == path) {
+                    step.problems.push(Problem::Other("differs from the copy kept".to_string()));
+                }
+            }
+        }
+        let blocked = plan.steps.len() - plan.ready().count();
+        self.message = format!(
+            "Dry run: {} of {} files ready, {} would be freed. Nothing has been changed yet.",
+            plan.ready().count(),
+            plan.steps.len(),
+            format_file_size(plan.bytes_freed())
+        );
+        self.message_type = if blocked == 0 { MessageType::Info } else { MessageType::Warning };
+        self.plan = Some(plan);
+        self.state = AppState::ReviewingPlan;
+    }
+
+    fn execute_plan(&mut self, plan: &Plan) {
+        self.state = AppState::Processing;
+        let (verb, past) = match plan.operation {
+            Operation::Delete => ("delete", "deleted"),
+            Operation::Archive => ("archive", "archived"),
+            Operation::Trash => ("trash", "trashed"),
+            Operation::Link | Operation::Undo => ("link", "linked"),
+        };
+        let outcome = plan::execute(plan);
+        self.finish_batch(verb, past, outcome);
+        self.load_archive();
+        self.load_trash();
+    }
+
+    // Pairs each selected copy with the first unselected copy of its group,
+    // along with the size each link frees
+    fn dedupe_pairs(&self) -> (Vec<(String, String)>, std::collections::HashMap<String, u64>) {
+        let mut pairs = Vec::new();
+        let mut sizes = std::collections::HashMap::new();
+        
+        for (group, selected) in self.duplicates.iter().zip(&self.duplicate_selected) {
+            let Some(keeper) = selected.iter().position(|&s| !s) else {
+                continue;
+            };
+            
+            for (file, _) in group.files.iter().zip(selected).filter(|(_, &s)| s) {
+                pairs.push((group.files[keeper].path.clone(), file.path.clone()));
+                sizes.insert(file.path.clone(), group.size);
+            }
+        }
+        
+        (pairs, sizes)
+    }
+
+    // Links each selected copy to the first unselected copy of its group
+    fn dedupe_selection(&mut self) {
+        self.state = AppState::Processing;
+        let (pairs, sizes) = self.dedupe_pairs();
+        let outcome = link_duplicates(&pairs, LinkMode::Auto);
+        let linked = outcome.succeeded();
+        let freed: u64 = linked.iter().filter_map(|path| sizes.get(path)).sum();
+        let failed_count = outcome.failed_count();
+        
+        self.state = AppState::Normal;
+        if failed_count == 0 {
+            self.message = format!("Deduplicated {} files in place, freeing {}.", linked.len(), format_file_size(freed));
+            self.message_type = MessageType::Success;
+        } else {
+            self.message = format!("Deduplicated {} files, failed to link {} files.", linked.len(), failed_count);
+            self.message_type = MessageType::Error;
+        }
+        
+        // The copies are still there, now as links, so the scan stands
+        self.forget_duplicates(&linked);
+    }
+
+    // Drops handled copies from the duplicate groups
+    fn forget_duplicates(&mut self, paths: &[String]) {
+        let groups = std::mem::take(&mut self.duplicates).into_iter()
+            .map(|mut group| {
+                group.files.retain(|f| !paths.contains(&f.path));
+                group
+            })
+            .filter(|group| group.files.len() > 1)
+            .collect();
+        self.set_duplicates(groups);
+    }
+
+    // Drops files the app itself removed from the scanned list, rather than
+    // scanning again; a directory takes everything under it
+    fn forget_files(&mut self, paths: &[String]) {
+        if !paths.is_empty() {
+            let gone: std::collections::HashSet<&std::path::Path> = paths.iter().map(std::path::Path::new).collect();
+            self.all_files.retain(|f| !std::path::Path::new(&f.path).ancestors().any(|path| gone.contains(path)));
+            self.dir_tree = None;
+            self.suggested_rules = suggest_rules_for_files(&self.all_files);
+            self.apply_sort_and_filter();
+            self.update_stats();
+        }
+        
+        // A scan under way may have listed them before they went
+        if self.scan.is_some() {
+            self.scan_and_filter(false);
+        }
+    }
+
+    // Brings the list up to date with what the watcher saw, keeping the
+    // selection
+    fn apply_file_changes(&mut self, changes: Vec<Change>) {
+        let selected: std::collections::HashSet<String> = self.files.iter()
+            .zip(&self.selected)
+            .filter(|(_, &is_selected)| is_selected)
+            .map(|(file, _)| file.path.clone())
+            .collect();
+        
+        apply_changes(&mut self.all_files, changes);
+        self.dir_tree = None;
+        self.suggested_rules = suggest_rules_for_files(&self.all_files);
+        self.apply_sort_and_filter();
+        self.update_stats();
+        
+        self.selected = self.files.iter().map(|file| selected.contains(&file.path)).collect();
+        self.update_selection_stats();
+    }
+
+    // Rebuilds the Folders view's totals after the scanned files (which
+    // drop the tree) or the age basis changed, keeping the place and the
+    // ticks that still exist
+    fn update_dir_tree(&mut self) {
+        if self.dir_tree.as_ref().is_some_and(|tree| tree.basis == self.rule.age_basis) {
+            return;
+        }
+        let tree = DirTree::build(&self.folder_path, &self.all_files, self.rule.age_basis);
+        let paths: std::collections::HashSet<&str> = self.all_files.iter().map(|f| f.path.as_str()).collect();
+        self.folder_selected.retain(|path| tree.find(path).is_some() || paths.contains(path.as_str()));
+        
+        // Step up from a directory that has gone
+        let shown = std::path::Path::new(&self.folder_view)
+            .ancestors()
+            .find_map(|dir| tree.find(&dir.display().to_string()))
+            .unwrap_or(0);
+        self.folder_view = tree.nodes[shown].path.clone();
+        self.dir_tree = Some(tree);
+    }
+
+    fn set_duplicates(&mut self, groups: Vec<DuplicateGroup>) {
+        self.duplicate_selected = groups.iter().map(|g| vec![false; g.files.len()]).collect();
+        self.duplicates = groups;
+        self.update_selection_stats();
+    }
+
+    fn load_archive(&mut self) {
+        match archive::list_entries() {
+            Ok(entries) => {
+                self.archive_selected = vec![false; entries.len()];
+                self.archive_entries = entries;
+            }
+            Err(err) => {
+                self.message = format!("Could not read the archive manifest: {}", err);
+                self.message_type = MessageType::Error;
+            }
+        }
+        self.update_selection_stats();
+    }
+
+    fn load_trash(&mut self) {
+        match trashcan::list_entries() {
+            Ok(entries) => {
+                self.trash_selected = vec![false; entries.len()];
+                self.trash_entries = entries;
+            }
+            Err(err) => {
+                self.message = format!("Could not read the trash: {}", err);
+                self.message_type = MessageType::Error;
+            }
+        }
+        self.update_selection_stats();
+    }
+
+    // Restores the selected archive or trash entries, to their original paths
+    // when no target folder is given
+    fn restore_selection(&mut self, target: Option<&std::path::Path>) {
+        let (results, source): (Vec<Result<(), FileActionError>>, &str) = if self.view_mode == ViewMode::Trash {
+            let results = self.selected_trash_entries().into_iter()
+                .map(|entry| trashcan::restore(entry, target).map(|_| ()))
+                .collect();
+            (results, "the trash")
+        } else {
+            let results = self.selected_archive_entries().into_iter()
+                .map(|entry| archive::restore(&entry.id, target).map(|_| ()))
+                .collect();
+            (results, "the archive")
+        };
+        
+        if results.is_empty() {
+            self.message = "Nothing selected to restore.".to_string();
+            self.message_type = MessageType::Warning;
+            return;
+        }
+        
+        let restored_count = results.iter().filter(|result| result.is_ok()).count();
+        let failed_count = results.len() - restored_count;
+        match results.into_iter().filter_map(Result::err).last() {
+            None => {
+                self.message = format!("Restored {} files from {}.", restored_count, source);
+                self.message_type = MessageType::Success;
+            }
+            Some(err) => {
+                self.message = format!("Restored {} files, failed to restore {} files ({}).", restored_count, failed_count, err);
+                self.message_type = MessageType::Error;
+            }
+        }
+        
+        self.load_archive();
+        self.load_trash();
+        self.scan_and_filter(false);
+    }
+
+    fn purge_selection(&mut self) {
+        self.state = AppState::Processing;
+        let entries: Vec<TrashEntry> = self.selected_trash_entries().into_iter().cloned().collect();
+        let outcome = purge_trash(&entries);
+        let failed_count = outcome.failed_count();
+        
+        self.state = AppState::Normal;
+        if failed_count == 0 {
+            self.message = format!("Permanently deleted {} items from the trash.", outcome.results.len());
+            self.message_type = MessageType::Success;
+        } else {
+            self.message = format!("Deleted {} items, failed to delete {} items.", outcome.results.len() - failed_count, failed_count);
+            self.message_type = MessageType::Error;
+        }
+        
+        self.load_trash();
+    }
+
+    // Drills down from the scanned folder like ncdu: subdirectories largest
+    // first, each with a bar for its share of the directory shown, then the
+    // files directly inside
+    fn view_folders(&self) -> Element<Message> {
+        let Some(tree) = &self.dir_tree else {
+            return text("Scan a folder to see how much each directory holds.").into();
+        };
+        let current = tree.find(&self.folder_view).unwrap_or(0);
+        let node = &tree.nodes[current];
+        let total = node.size.max(1) as f32;
+
+        let mut up = button("⬆ Up");
+        if let Some(parent) = node.parent {
+            up = up.on_press(Message::OpenFolder(tree.nodes[parent].path.clone()));
+        }
+        let toolbar = row![
+            up,
+            text(format!("{}: {} in {} files", node.path, format_file_size(node.size), node.file_count))
+                .width(Length::Fill),
+        ]
+        .spacing(10)
+        .padding(10)
+        .align_items(iced::Alignment::Center);
+
+        let header = container(
+            row![
+                text("Select").width(Length::Fixed(60.0)),
+                text("Name").width(Length::FillPortion(4)),
+                text("Share").width(Length::Fixed(120.0)),
+                text("Size").width(Length::Fixed(100.0)),
+                text("Files").width(Length::Fixed(80.0)),
+                text(format!("Oldest – newest ({})", self.rule.age_basis)).width(Length::Fixed(220.0)),
+            ]
+            .padding(5)
+        )
+        .style(theme::Container::Custom(Box::new(HeaderStyle)));
+
+        let is_selected = |path: &str| self.folder_selected.iter().any(|selected| selected == path);
+        let mut rows: Vec<(String, Element<Message>, u64, String, String)> = tree.children_by_size(current)
+            .into_iter()
+            .map(|child| {
+                let path = child.path.clone();
+                let open: Element<Message> = button(text(format!("📁 {}", child.name())))
+                    .on_press(Message::OpenFolder(path.clone()))
+                    .into();
+                (path, open, child.size, child.file_count.to_string(),
+                    format!("{} – {}", format_date(child.oldest_secs), format_date(child.newest_secs)))
+            })
+            .collect();
+        let mut files: Vec<&FileInfo> = node.files.iter().filter_map(|&index| self.all_files.get(index)).collect();
+        files.sort_by(|a, b| b.size.cmp(&a.size));
+        rows.extend(files.into_iter().map(|file| {
+            let name = std::path::Path::new(&file.path).file_name().unwrap_or_default().to_string_lossy();
+            let label: Element<Message> = text(format!("📄 {}", name)).into();
+            (file.path.clone(), label, file.size, String::new(), format_date(self.rule.age_basis.timestamp(file)))
+        }));
+
+        let entries = rows.into_iter().enumerate().fold(column![header], |col, (i, (path, label, size, count, dates))| {
+            let row_style = if i % 2 == 0 {
+                theme::Container::Custom(Box::new(EvenRowStyle))
+            } else {
+                theme::Container::Custom(Box::new(OddRowStyle))
+            };
+            let selected = is_selected(&path);
+            
+            col.push(
+                container(
+                    row![
+                        checkbox("", selected, move |val| Message::ToggleFolderEntry(path.clone(), val))
+                            .width(Length::Fixed(60.0)),
+                        container(label).width(Length::FillPortion(4)),
+                        progress_bar(0.0..=total, size as f32)
+                            .width(Length::Fixed(120.0))
+                            .height(Length::Fixed(10.0)),
+                        text(format_file_size(size)).width(Length::Fixed(100.0)),
+                        text(count).width(Length::Fixed(80.0)),
+                        text(dates).width(Length::Fixed(220.0)),
+                    ]
+                    .padding(5)
+                    .spacing(5)
+                    .align_items(iced::Alignment::Center)
+                )
+                .style(row_style)
+            )
+        });
+
+        column![toolbar, scrollable(entries).height(Length::FillPortion(1))].into()
+    }
+
+    fn view_archive(&self) -> Element<Message> {
+        let header = container(
+            row![
+                text("Select").width(Length::Fixed(60.0)),
+                text("Original Path").width(Length::FillPortion(5)),
+                text("Size").width(Length::Fixed(100.0)),
+                text("Archived").width(Length::Fixed(160.0)),
+            ]
+            .padding(5)
+        )
+        .style(theme::Container::Custom(Box::new(HeaderStyle)));
+
+        let entries = self.archive_entries.iter().enumerate().fold(column![header], |col, (i, entry)| {
+            let row_style = if i % 2 == 0 {
+                theme::Container::Custom(Box::new(EvenRowStyle))
+            } else {
+                theme::Container::Custom(Box::new(OddRowStyle))
+            };
+            
+            col.push(
+                container(
+                    row![
+                        checkbox("", self.archive_selected.get(i).copied().unwrap_or(false), move |val| Message::ToggleArchived(i, val))
+                            .width(Length::Fixed(60.0)),
+                        text(&entry.original_path).width(Length::FillPortion(5)),
+                        text(format_file_size(entry.size)).width(Length::Fixed(100.0)),
+                        text(entry.archived_at.get(..19).unwrap_or(&entry.archived_at).replace('T', " "))
+                            .width(Length::Fixed(160.0)),
+                    ]
+                    .padding(5)
+                    .spacing(5)
+                    .align_items(iced::Alignment::Center)
+                )
+                .style(row_style)
+            )
+        });
+
+        scrollable(entries).height(Length::FillPortion(1)).into()
+    }
+
+    fn view_details(&self, details: &FileDetails) -> Element<Message> {
+        let file = &details.file;
+        let verdict = match (details.explanation.matched, &details.skipped_by_scan) {
+            (_, Some(reason)) => format!("Not listed: {}.", reason),
+            (true, None) => "Matches the current rule.".to_string(),
+            (false, None) => "Does not match the current rule.".to_string(),
+        };
+        
+        let summary = column![
+            row![
+                text(&file.path).size(16).width(Length::Fill),
+                button("Close").on_press(Message::CloseDetails),
+            ]
+            .align_items(iced::Alignment::Center),
+            text(format!(
+                "{} | {} | accessed {} | modified {}",
+                format_file_size(file.size),
+                file.file_type,
+                file.last_accessed,
+                file.last_modified
+            ))
+            .size(12),
+            text(verdict),
+        ]
+        .spacing(5);
+        
+        let checks = details.explanation.checks.iter().fold(column![summary].spacing(2), |col, check| {
+            let color = if check.passed {
+                iced::Color::from_rgb(0.0, 0.6, 0.0)
+            } else {
+                iced::Color::from_rgb(0.8, 0.0, 0.0)
+            };
+            col.push(
+                row![
+                    text(if check.passed { "pass" } else { "fail" })
+                        .style(color)
+                        .width(Length::Fixed(40.0)),
+                    text(&check.name).width(Length::Fixed(160.0)),
+                    text(&check.observed).width(Length::FillPortion(1)),
+                    text(&check.expected).width(Length::FillPortion(1)),
+                ]
+                .spacing(10)
+                .padding(iced::Padding { top: 0.0, right: 0.0, bottom: 0.0, left: 20.0 * check.depth as f32 })
+            )
+        });
+        
+        container(scrollable(checks.padding(10)))
+            .style(theme::Container::Box)
+            .height(Length::Fixed(220.0))
+            .width(Length::Fill)
+            .into()
+    }
+
+    fn view_rule_sidebar(&self) -> Element<Message> {
+        let sections = [
+            ("Built-in", RuleSource::Predefined, &self.predefined_rules),
+            ("Saved", RuleSource::Saved, &self.saved_rules),
+            ("Suggested", RuleSource::Suggested, &self.suggested_rules),
+        ];
+        
+        let list = sections.into_iter().fold(column![].spacing(5), |col, (title, source, rules)| {
+            let mut col = col.push(text(title).size(16));
+            if rules.is_empty() {
+                let hint = match source {
+                    RuleSource::Saved => "Save a rule from the editor",
+                    RuleSource::Suggested => "Scan a folder for suggestions",
+                    RuleSource::Predefined => "",
+                };
+                col = col.push(text(hint).size(12));
+            }
+            rules.iter().enumerate().fold(col, |col, (i, rule)| {
+                col.push(
+                    button(column![text(&rule.name), text(&rule.description).size(11)])
+                        .on_press(Message::UseRule(source, i))
+                        .width(Length::Fill)
+                        .style(theme::Button::Secondary)
+                )
+            })
+        });
+
+        scrollable(list.padding(5))
+            .width(Length::Fixed(220.0))
+            .height(Length::Fill)
+            .into()
+    }
+
+    fn view_rule_editor(&self) -> Element<Message> {
+        let form = &self.rule_form;
+        let field = |label: &str, placeholder: &str, value: &str, which: RuleField| {
+            row![
+                text(label).width(Length::Fixed(110.0)),
+                text_input(placeholder, value)
+                    .on_input(move |value| Message::EditRule(which, value))
+                    .width(Length::Fill),
+            ]
+            .spacing(10)
+            .align_items(iced::Alignment::Center)
+        };
+
+        let is_saved = self.saved_rules.iter().any(|rule| rule.name.eq_ignore_ascii_case(form.name.trim()));
+        let mut buttons = row![
+            button("Apply").on_press(Message::ApplyRuleForm),
+            button("Save Rule").on_press(Message::SaveRule),
+        ]
+        .spacing(10);
+        if is_saved {
+            buttons = buttons.push(button("Delete Saved Rule").on_press(Message::DeleteRule));
+        }
+
+        container(
+            column![
+                row![
+                    field("Name:", "Name to save the rule under", &form.name, RuleField::Name),
+                    field("Max age (days):", "30", &form.max_age, RuleField::MaxAge),
+                    text("since"),
+                    pick_list(&AgeBasis::ALL[..], Some(form.age_basis), Message::ChangeAgeBasis),
+                ]
+                .spacing(20)
+                .align_items(iced::Alignment::Center),
+                row![
+                    field("Min size (MB):", "0", &form.min_size, RuleField::MinSize),
+                    field("Max size (MB):", "no limit", &form.max_size, RuleField::MaxSize),
+                ]
+                .spacing(20),
+                row![
+                    field("Only types:", "e.g. Video, Archive", &form.types, RuleField::Types),
+                    field("Never types:", "e.g. Document", &form.exclude_types, RuleField::ExcludeTypes),
+                ]
+                .spacing(20),
+                row![
+                    field("Match paths:", "e.g. */Downloads/*, *.iso", &form.patterns, RuleField::Patterns),
+                    field("Skip paths:", "e.g. */keep/*", &form.exclude_patterns, RuleField::ExcludePatterns),
+                ]
+                .spacing(20),
+                field(
+                    "Expression:",
+                    "e.g. (in:~/Downloads older:30d or name:*.iso larger:1G) and not in:~/keep",
+                    &form.expression,
+                    RuleField::Expression,
+                ),
+                row![
+                    field("Archive to:", "default archive", &form.archive_to, RuleField::ArchiveTo),
+                    checkbox("Hidden files", form.include_hidden, |value| Message::ToggleRuleFlag(RuleFlag::Hidden, value)),
+                    checkbox("Read-only files", form.include_readonly, |value| Message::ToggleRuleFlag(RuleFlag::Readonly, value)),
+                    checkbox("Executables", form.include_executable, |value| Message::ToggleRuleFlag(RuleFlag::Executable, value)),
+                ]
+                .spacing(20)
+                .align_items(iced::Alignment::Center),
+                buttons,
+            ]
+            .spacing(8)
+            .padding(10)
+        )
+        .style(theme::Container::Box)
+        .into()
+    }
+
+    fn view_plan(&self, plan: &Plan) -> Element<Message> {
+        let header = container(
+            row![
+                text("Operation").width(Length::Fixed(80.0)),
+                text("File Path").width(Length::FillPortion(4)),
+                text("Size").width(Length::Fixed(100.0)),
+                text("Destination").width(Length::FillPortion(3)),
+                text("Problems").width(Length::FillPortion(2)),
+            ]
+            .padding(5)
+        )
+        .style(theme::Container::Custom(Box::new(HeaderStyle)));
+
+        let operation = format!("{:?}", plan.operation);
+        let steps = plan.steps.iter().enumerate().fold(column![header], |col, (i, step)| {
+            let row_style = if i % 2 == 0 {
+                theme::Container::Custom(Box::new(EvenRowStyle))
+            } else {
+                theme::Container::Custom(Box::new(OddRowStyle))
+            };
+            let problems: Vec<String> = step.problems.iter().map(|p| p.to_string()).collect();
+            let problems = if problems.is_empty() {
+                text("ready")
+            } else {
+                text(problems.join(", ")).style(iced::Color::from_rgb(0.8, 0.0, 0.0))
+            };
+            
+            col.push(
+                container(
+                    row![
+                        text(&operation).width(Length::Fixed(80.0)),
+                        text(&step.path).width(Length::FillPortion(4)),
+                        text(format_file_size(step.bytes)).width(Length::Fixed(100.0)),
+                        text(step.destination.as_deref().unwrap_or("-")).width(Length::FillPortion(3)),
+                        problems.width(Length::FillPortion(2)),
+                    ]
+                    .padding(5)
+                    .spacing(5)
+                    .align_items(iced::Alignment::Center)
+                )
+                .style(row_style)
+            )
+        });
+
+        scrollable(steps).height(Length::FillPortion(1)).into()
     }
+
+    fn view_trash(&self) -> Element<Message> {
+        let total: u64 = self.trash_entries.iter().map(|e| e.size).sum();
+        let toolbar = row![
+            text(format!("{} items, {}", self.trash_entries.len(), format_file_size(total))).width(Length::Fill),
+            text("Older than (days):"),
+            text_input("30", &self.purge_age)
+                .on_input(Message::ChangePurgeAge)
+                .width(Length::Fixed(60.0)),
+            button("Select").on_press(Message::SelectOlderThan),
+            text("Keep under (MB):"),
+            text_input("1024", &self.trash_cap)
+                .on_input(Message::ChangeTrashCap)
+                .width(Length::Fixed(80.0)),
+            button("Select Oldest").on_press(Message::SelectOverCap),
+        ]
+        .spacing(10)
+        .padding(10)
+        .align_items(iced::Alignment::Center);
+
+        let header = container(
+            row![
+                text("Select").width(Length::Fixed(60.0)),
+                text("Original Path").width(Length::FillPortion(5)),
+                text("Size").width(Length::Fixed(100.0)),
+                text("Deleted").width(Length::Fixed(160.0)),
+            ]
+            .padding(5)
+        )
+        .style(theme::Container::Custom(Box::new(HeaderStyle)));
+
+        let entries = self.trash_entries.iter().enumerate().fold(column![header], |col, (i, entry)| {
+            let row_style = if i % 2 == 0 {
+                theme::Container::Custom(Box::new(EvenRowStyle))
+            } else {
+                theme::Container::Custom(Box::new(OddRowStyle))
+            };
+            let deleted = entry.deleted_at
+                .map(|deleted| deleted.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            
+            col.push(
+                container(
+                    row![
+                        checkbox("", self.trash_selected.get(i).copied().unwrap_or(false), move |val| Message::ToggleTrashed(i, val))
+                            .width(Length::Fixed(60.0)),
+                        text(entry.original_path.display().to_string()).width(Length::FillPortion(5)),
+                        text(format_file_size(entry.size)).width(Length::Fixed(100.0)),
+                        text(deleted).width(Length::Fixed(160.0)),
+                    ]
+                    .padding(5)
+                    .spacing(5)
+                    .align_items(iced::Alignment::Center)
+                )
+                .style(row_style)
+            )
+        });
+
+        column![toolbar, scrollable(entries).height(Length::FillPortion(1))]
+            .height(Length::FillPortion(1))
+            .into()
+    }
+
+    fn view_duplicates(&self) -> Element<Message> {
+        let mut find = button(if self.finding_duplicates { "Comparing..." } else { "Find Duplicates" });
+        if !self.finding_duplicates {
+            find = find.on_press(Message::FindDuplicates);
+        }
+
+        let reclaimable: u64 = self.duplicates.iter().map(|g| g.reclaimable_bytes()).sum();
+        let toolbar = column![
+            row![
+                find,
+                text(format!("{} groups, {} reclaimable", self.duplicates.len(), format_file_size(reclaimable)))
+                    .width(Length::Fill),
+            ]
+            .spacing(10)
+            .align_items(iced::Alignment::Center),
+
+            row![
+                text("Keep:").width(Length::Fixed(60.0)),
+                button("Oldest").on_press(Message::ApplyKeepPolicy(KeepPolicy::Oldest)),
+                button("Newest").on_press(Message::ApplyKeepPolicy(KeepPolicy::Newest)),
+                button("Shortest Path").on_press(Message::ApplyKeepPolicy(KeepPolicy::ShortestPath)),
+                button("In Preferred Folder")
+                    .on_press(Message::ApplyKeepPolicy(KeepPolicy::PreferDirectory(self.preferred_dir.clone()))),
+                text_input("Preferred folder, e.g. /home/me/Photos", &self.preferred_dir)
+                    .on_input(Message::ChangePreferredDir)
+                    .width(Length::Fill),
+            ]
+            .spacing(10)
+            .align_items(iced::Alignment::Center),
+        ]
+        .spacing(10)
+        .padding(10);
+
+        let groups = self.duplicates.iter().enumerate().fold(column![], |col, (g, group)| {
+            let header = container(
+                text(format!(
+                    "{} copies of {} ({} reclaimable)",
+                    group.files.len(),
+                    format_file_size(group.size),
+                    format_file_size(group.reclaimable_bytes())
+                ))
+            )
+            .padding(5)
+            .width(Length::Fill)
+            .style(theme::Container::Custom(Box::new(HeaderStyle)));
+
+            let selected = &self.duplicate_selected[g];
+            let rows = group.files.iter().enumerate().fold(column![header], |col, (i, file)| {
+                col.push(
+                    row![
+                        checkbox("", selected.get(i).copied().unwrap_or(false), move |val| Message::ToggleDuplicate(g, i, val))
+                            .width(Length::Fixed(60.0)),
+                        text(&file.path).width(Length::FillPortion(5)),
+                        text(&file.last_modified).width(Length::Fixed(160.0)),
+                    ]
+                    .padding(5)
+                    .spacing(5)
+                    .align_items(iced::Alignment::Center)
+                )
+            });
+
+            col.push(rows)
+        })
+        .spacing(10);
+
+        column![
+            toolbar,
+            scrollable(groups).height(Length::FillPortion(1)),
+        ]
+        .into()
+    }
+}
+
+// A timestamp as a local date, or nothing for an empty directory
+fn format_date(secs: u64) -> String {
+    match chrono::DateTime::from_timestamp(secs as i64, 0) {
+        Some(time) if secs > 0 => time.with_timezone(&chrono::Local).format("%Y-%m-%d").to_string(),
+        _ => String::new(),
+    }
+}
+
+// Runs the walk on a worker thread and forwards its progress as messages.
+// Directories unchanged since the last scan of the folder come from its index
+fn scan_subscription(id: u64, folder: String, options: ScanOptions, cancel: Arc<AtomicBool>) -> Subscription<Message> {
+    subscription::channel(id, 100, move |mut output| async move {
+        let (sender, mut receiver) = mpsc::unbounded();
+        
+        std::thread::spawn(move || {
+            let files = scan_folder_indexed(&folder, &options, &cancel, |progress| {
+                let _ = sender.unbounded_send(Message::ScanProgressed(id, progress.clone()));
+            });
+            let _ = sender.unbounded_send(Message::ScanFinished(id, files));
+        });
+        
+        while let Some(message) = receiver.next().await {
+            let _ = output.send(message).await;
+        }
+        
+        loop {
+            iced::futures::future::pending::<()>().await;
+        }
+    })
+}
+
+// Watches the folder on a worker thread until the subscription is dropped,
+// forwarding each batch of changes
+fn watch_subscription(folder: String, options: ScanOptions) -> Subscription<Message> {
+    subscription::channel(("watch", folder.clone()), 100, move |mut output| async move {
+        let (sender, mut receiver) = mpsc::unbounded();
+        
+        std::thread::spawn(move || {
+            let mut watcher = match Watcher::new(&folder, &options) {
+                Ok(watcher) => watcher,
+                Err(err) => {
+                    let _ = sender.unbounded_send(Message::WatchFailed(folder, err.to_string()));
+                    return;
+                }
+            };
+            let _ = sender.unbounded_send(Message::WatchReady(folder.clone()));
+            
+            // Wake up now and then to notice the subscription has gone
+            while !sender.is_closed() {
+                match watcher.read_changes(Duration::from_secs(1)) {
+                    Ok(changes) if changes.is_empty() => {}
+                    Ok(changes) => {
+                        let _ = sender.unbounded_send(Message::FilesChanged(folder.clone(), changes));
+                    }
+                    Err(err) => {
+                        let _ = sender.unbounded_send(Message::WatchFailed(folder, err.to_string()));
+                        return;
+                    }
+                }
+            }
+        });
+        
+        while let Some(message) = receiver.next().await {
+            let _ = output.send(message).await;
+        }
+        
+        loop {
+            iced::futures::future::pending::<()>().await;
+        }
+    })
 }
 
 struct HeaderStyle;
@@ -0,0 +1,196 @@
+//! Mount points and how they keep access times.
+//!
+//! Most Linux filesystems are mounted `relatime`, which only updates a
+//! file's access time once a day, and many are `noatime`, which never does.
+//! Rules that age files by last access are misleading there, so scans record
+//! the [`AtimeMode`] of the mount each file lives on and [`atime_warning`]
+//! says when it matters.
+
+use crate::rules::{days_to_secs, AgeBasis, RuleConfig};
+use crate::scanner::FileInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How a mount updates access times.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AtimeMode {
+    /// Every read updates the access time (`strictatime`).
+    Strict,
+    /// Updated when older than the last change, or at most once a day. The
+    /// kernel default.
+    Relatime,
+    /// Never updated: `noatime`, or a read-only mount.
+    Noatime,
+    /// The mount could not be found.
+    #[default]
+    Unknown,
+}
+
+impl AtimeMode {
+    /// Whether access times can tell files apart by `age_secs`.
+    pub fn reliable_for(self, age_secs: u64) -> bool {
+        match self {
+            AtimeMode::Strict | AtimeMode::Unknown => true,
+            AtimeMode::Relatime => age_secs >= 86400,
+            AtimeMode::Noatime => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Mount {
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    pub atime: AtimeMode,
+}
+
+/// The mounts in `/proc/self/mountinfo`, with the mode found for each
+/// device cached so a scan only searches the table once per filesystem.
+#[derive(Clone, Debug, Default)]
+pub struct MountTable {
+    mounts: Vec<Mount>,
+    by_device: HashMap<u64, AtimeMode>,
+}
+
+impl MountTable {
+    /// Reads the current process's mounts. The table is empty where
+    /// mountinfo is not available, and every file is then [`AtimeMode::Unknown`].
+    pub fn load() -> Self {
+        fs::read_to_string("/proc/self/mountinfo")
+            .map(|text| Self::parse(&text))
+            .unwrap_or_default()
+    }
+
+    /// Parses mountinfo lines:
+    /// `id parent major:minor root mount-point options [optional...] - fstype source super-options`
+    pub fn parse(text: &str) -> Self {
+        let mounts = text.lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split(' ').collect();
+                let separator = fields.iter().position(|&field| field == "-")?;
+                let mount_point = unescape(fields.get(4)?);
+                let options: Vec<&str> = fields.get(5)?.split(',').collect();
+                let atime = if options.contains(&"noatime") || options.contains(&"ro") {
+                    AtimeMode::Noatime
+                } else if options.contains(&"relatime") {
+                    AtimeMode::Relatime
+                } else {
+                    AtimeMode::Strict
+                };
+                Some(Mount {
+                    mount_point: PathBuf::from(mount_point),
+                    fs_type: fields.get(separator + 1)?.to_string(),
+                    atime,
+                })
+            })
+            .collect();
+        Self { mounts, by_device: HashMap::new() }
+    }
+
+    /// The mount `path` is on: the longest mount point it is under. Later
+    /// mounts over the same point hide earlier ones.
+    pub fn mount_of(&self, path: &Path) -> Option<&Mount> {
+        let path = std::path::absolute(path).ok()?;
+        self.mounts.iter()
+            .filter(|mount| path.starts_with(&mount.mount_point))
+            .max_by_key(|mount| mount.mount_point.components().count())
+    }
+
+    /// The access-time mode for a file on `device` (its `st_dev`). Bind
+    /// mounts of one device with different options share the first answer.
+    pub fn atime_mode(&mut self, path: &Path, device: u64) -> AtimeMode {
+        if let Some(&mode) = self.by_device.get(&device) {
+            return mode;
+        }
+        let mode = self.mount_of(path).map(|mount| mount.atime).unwrap_or_default();
+        self.by_device.insert(device, mode);
+        mode
+    }
+}
+
+/// Explains why `rule` would give misleading results for `files`, if it
+/// ages them by access time and some sit on mounts that don't keep it.
+pub fn atime_warning(files: &[FileInfo], rule: &RuleConfig) -> Option<String> {
+    if !matches!(rule.age_basis, AgeBasis::Access | AgeBasis::Oldest) {
+        return None;
+    }
+
+    // The shortest age the rule tells apart decides whether daily
+    // relatime updates are precise enough
+    let mut ages: Vec<u64> = rule.expression.as_deref()
+        .and_then(|text| crate::expr::parse(text).ok())
+        .map(|expression| expression.ages())
+        .unwrap_or_default();
+    if rule.max_age_days > 0 {
+        ages.push(days_to_secs(rule.max_age_days).unwrap_or(u64::MAX));
+    }
+    let shortest = ages.into_iter().min()?;
+
+    let affected: Vec<&FileInfo> = files.iter().filter(|file| !file.atime_mode.reliable_for(shortest)).collect();
+    if affected.is_empty() {
+        return None;
+    }
+
+    let reason = if affected.iter().any(|file| file.atime_mode == AtimeMode::Noatime) {
+        "mounted noatime or read-only, so their access times never change"
+    } else {
+        "mounted relatime, so their access times only change once a day"
+    };
+    Some(format!(
+        "{} of {} files are on filesystems {}; ages by last access are misleading for them. Count age from the modification time instead",
+        affected.len(),
+        files.len(),
+        reason
+    ))
+}
+
+// mountinfo writes spaces, tabs, newlines and backslashes as octal escapes
+fn unescape(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let raw = field.as_bytes();
+    let mut i = 0;
+    while i < raw.len() {
+        let octal = raw.get(i + 1..i + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match (raw[i], octal) {
+            (b'\\', Some(byte)) => {
+                bytes.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                bytes.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let table = MountTable::parse(
+            "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+             30 22 8:2 / /home rw,noatime shared:2 - ext4 /dev/sda2 rw\n\
+             31 22 8:3 / /mnt/my\\040disk rw,nosuid - xfs /dev/sda3 rw\n\
+             32 22 0:5 / /media/cd ro,relatime - iso9660 /dev/sr0 ro\n",
+        );
+
+        let mode = |path: &str| table.mount_of(Path::new(path)).map(|mount| mount.atime);
+        assert_eq!(mode("/etc/passwd"), Some(AtimeMode::Relatime));
+        assert_eq!(mode("/home/me/a.txt"), Some(AtimeMode::Noatime));
+        assert_eq!(mode("/homework/a.txt"), Some(AtimeMode::Relatime));
+        assert_eq!(mode("/mnt/my disk/a.txt"), Some(AtimeMode::Strict));
+        assert_eq!(mode("/media/cd/a.txt"), Some(AtimeMode::Noatime));
+        assert_eq!(table.mount_of(Path::new("/home")).unwrap().fs_type, "ext4");
+
+        assert!(AtimeMode::Relatime.reliable_for(30 * 86400));
+        assert!(!AtimeMode::Relatime.reliable_for(3600));
+    }
+}
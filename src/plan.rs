@@ -0,0 +1,363 @@
+//! Dry runs: what a cleanup action would do, worked out without touching
+//! anything.
+//!
+//! A [`Plan`] lists every path with its operation, destination and size,
+//! plus whatever would stop it from going through. Plans can be saved as
+//! JSON for review and later [`execute`]d exactly as written; files that
+//! changed since the plan was made are left alone.
+
+use crate::actions::{archive_files_with, delete_files, link_duplicates, trash_files, BatchOutcome, FileActionError, LinkMode};
+use crate::archive::{self, ArchiveOptions};
+use crate::journal::Operation;
+use crate::protect::ProtectedPaths;
+use crate::trashcan;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub created_at: String,
+    pub operation: Operation,
+    /// Bundle settings, for archive plans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<ArchiveOptions>,
+    pub steps: Vec<PlanStep>,
+}
+
+/// One file in a plan.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub path: String,
+    /// Bundle directory, trash directory, or the copy a duplicate is linked to.
+    pub destination: Option<String>,
+    /// Bytes the step removes from where the file is now.
+    pub bytes: u64,
+    /// Modification time when planned; a different one at execution means
+    /// the file changed and the step is skipped.
+    pub mtime: u64,
+    pub problems: Vec<Problem>,
+}
+
+/// Something that would make a step fail.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Problem {
+    Missing,
+    ReadOnly,
+    InUse,
+    InsufficientSpace,
+    Protected,
+    Other(String),
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing"),
+            Problem::ReadOnly => write!(f, "read-only"),
+            Problem::InUse => write!(f, "in use"),
+            Problem::InsufficientSpace => write!(f, "not enough space at destination"),
+            Problem::Protected => write!(f, "protected path"),
+            Problem::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Plan {
+    fn new(operation: Operation, steps: Vec<PlanStep>) -> Self {
+        Self {
+            created_at: chrono::Local::now().to_rfc3339(),
+            operation,
+            archive: None,
+            steps,
+        }
+    }
+
+    /// Steps that would go ahead.
+    pub fn ready(&self) -> impl Iterator<Item = &PlanStep> {
+        self.steps.iter().filter(|step| step.problems.is_empty())
+    }
+
+    /// Bytes the steps without problems would remove.
+    pub fn bytes_freed(&self) -> u64 {
+        self.ready().map(|step| step.bytes).sum()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), FileActionError> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| FileActionError::Other(e.to_string()))?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, FileActionError> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|e| FileActionError::Other(format!("Not a valid plan: {}", e)))
+    }
+}
+
+/// What [`delete_files`] would do.
+pub fn plan_delete(paths: &[String]) -> Plan {
+    let checks = Checks::new();
+    Plan::new(Operation::Delete, paths.iter().map(|path| step(path, None, &checks, true)).collect())
+}
+
+/// What [`archive_files_with`] would do with these options.
+pub fn plan_archive(paths: &[String], options: &ArchiveOptions) -> Plan {
+    let checks = Checks::new();
+    let dir = options.destination.clone().unwrap_or_else(archive::bundles_dir);
+
+    let mut steps: Vec<PlanStep> = paths.iter()
+        .map(|path| step(path, Some(dir.display().to_string()), &checks, true))
+        .collect();
+
+    // The default archive directory is created on demand, so measure the
+    // closest directory that exists
+    let space = match dir.ancestors().find(|ancestor| ancestor.is_dir()) {
+        Some(existing) if options.destination.is_none() || existing == dir => {
+            archive::check_free_space(existing, paths).err()
+        }
+        _ => Some(FileActionError::Other("Archive destination is not a directory".to_string())),
+    };
+    if let Some(error) = space {
+        let problem = match error {
+            FileActionError::InsufficientSpace => Problem::InsufficientSpace,
+            other => Problem::Other(other.to_string()),
+        };
+        for step in &mut steps {
+            step.problems.push(problem.clone());
+        }
+    }
+
+    Plan { archive: Some(options.clone()), ..Plan::new(Operation::Archive, steps) }
+}
+
+/// What [`trash_files`] would do.
+pub fn plan_trash(paths: &[String]) -> Plan {
+    let checks = Checks::new();
+    let steps = paths.iter()
+        .map(|path| {
+            let trash_dir = trashcan::planned_trash_dir(Path::new(path));
+            let mut step = step(path, trash_dir.as_ref().map(|dir| dir.display().to_string()), &checks, false);
+            if trash_dir.is_none() && !step.problems.contains(&Problem::Missing) {
+                step.problems.push(Problem::Other("no usable trash on this volume".to_string()));
+            }
+            step
+        })
+        .collect();
+    Plan::new(Operation::Trash, steps)
+}
+
+/// What [`link_duplicates`] would do with these `(original, duplicate)` pairs.
+pub fn plan_link(pairs: &[(String, String)]) -> Plan {
+    let checks = Checks::new();
+    let steps = pairs.iter()
+        .map(|(original, duplicate)| {
+            let mut step = step(duplicate, Some(absolute(original)), &checks, false);
+            if fs::metadata(original).is_err() {
+                step.problems.push(Problem::Other("the copy to keep is missing".to_string()));
+            }
+            step
+        })
+        .collect();
+    Plan::new(Operation::Link, steps)
+}
+
+/// Carries out a plan exactly as written, through the usual journaled batch
+/// actions. Steps with problems, steps that would now run into one (the
+/// file has been protected, made read-only or opened since), and steps whose
+/// file has changed since planning are reported as failures without being
+/// touched. Results follow the plan's order.
+pub fn execute(plan: &Plan) -> BatchOutcome {
+    let checks = Checks::new();
+    let readonly_blocks = matches!(plan.operation, Operation::Delete | Operation::Archive);
+
+    let mut skipped: HashMap<&str, FileActionError> = HashMap::new();
+    for step in &plan.steps {
+        if !step.problems.is_empty() {
+            skipped.insert(&step.path, FileActionError::Other(format!("Skipped: {}", describe(&step.problems))));
+            continue;
+        }
+        let problems = problems(&step.path, &checks, readonly_blocks);
+        if !problems.is_empty() {
+            skipped.insert(&step.path, FileActionError::Other(format!("Skipped, now {}", describe(&problems))));
+        } else if current_state(&step.path) != Some((step.bytes, step.mtime)) {
+            skipped.insert(&step.path, FileActionError::ContentMismatch);
+        }
+    }
+
+    let runnable: Vec<&PlanStep> = plan.steps.iter()
+        .filter(|step| !skipped.contains_key(step.path.as_str()))
+        .collect();
+    let paths: Vec<String> = runnable.iter().map(|step| step.path.clone()).collect();
+
+    let outcome = if runnable.is_empty() {
+        BatchOutcome::default()
+    } else {
+        match plan.operation {
+            Operation::Delete => delete_files(&paths),
+            Operation::Archive => archive_files_with(&paths, &plan.archive.clone().unwrap_or_default()),
+            Operation::Trash => trash_files(&paths),
+            Operation::Link => {
+                let pairs: Vec<(String, String)> = runnable.iter()
+                    .map(|step| (step.destination.clone().unwrap_or_default(), step.path.clone()))
+                    .collect();
+                link_duplicates(&pairs, LinkMode::Auto)
+            }
+            Operation::Undo => BatchOutcome {
+                batch: 0,
+                results: paths.iter()
+                    .map(|path| (path.clone(), Err(FileActionError::Other("Undo cannot be planned".to_string()))))
+                    .collect(),
+            },
+        }
+    };
+
+    let mut results: HashMap<String, Result<(), FileActionError>> = outcome.results.into_iter().collect();
+    BatchOutcome {
+        batch: outcome.batch,
+        results: plan.steps.iter()
+            .map(|step| {
+                let result = match skipped.remove(step.path.as_str()) {
+                    Some(error) => Err(error),
+                    None => results.remove(&step.path).unwrap_or(Err(FileActionError::FileNotFound)),
+                };
+                (step.path.clone(), result)
+            })
+            .collect(),
+    }
+}
+
+// What the steps of one plan are checked against, gathered once
+struct Checks {
+    open: HashSet<PathBuf>,
+    protected: ProtectedPaths,
+}
+
+impl Checks {
+    fn new() -> Self {
+        Self { open: open_files(), protected: ProtectedPaths::load() }
+    }
+}
+
+fn step(path: &str, destination: Option<String>, checks: &Checks, readonly_blocks: bool) -> PlanStep {
+    let (bytes, mtime) = current_state(path).unwrap_or((0, 0));
+    let problems = problems(path, checks, readonly_blocks);
+    PlanStep { path: absolute(path), destination, bytes, mtime, problems }
+}
+
+// Read-only files are refused by `delete_file`, which archiving also ends
+// with; moving them to the trash or over a link is fine
+fn problems(path: &str, checks: &Checks, readonly_blocks: bool) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    match fs::metadata(path) {
+        Err(_) => problems.push(Problem::Missing),
+        Ok(metadata) => {
+            if checks.protected.check(Path::new(path)).is_err() {
+                problems.push(Problem::Protected);
+            }
+            if readonly_blocks && metadata.permissions().readonly() {
+                problems.push(Problem::ReadOnly);
+            }
+            if fs::canonicalize(path).is_ok_and(|real| checks.open.contains(&real)) {
+                problems.push(Problem::InUse);
+            }
+        }
+    }
+    problems
+}
+
+fn describe(problems: &[Problem]) -> String {
+    problems.iter().map(|problem| problem.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+// Saved plans may be applied from another directory
+fn absolute(path: &str) -> String {
+    std::path::absolute(path)
+        .map(|absolute| absolute.display().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+// A directory counts everything under it
+fn current_state(path: &str) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let mtime = metadata.modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    match metadata.is_dir() {
+        true => Some((trashcan::tree_size(Path::new(path)), mtime)),
+        false => Some((metadata.len(), mtime)),
+    }
+}
+
+// Files held open by any process whose /proc/<pid>/fd we are allowed to read
+fn open_files() -> HashSet<PathBuf> {
+    let mut open = HashSet::new();
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return open;
+    };
+
+    for process in processes.flatten() {
+        if !process.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        let Ok(fds) = fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+        open.extend(fds.flatten().filter_map(|fd| fs::read_link(fd.path()).ok()));
+    }
+    open
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_round_trip() {
+        let dir = std::env::temp_dir().join(format!("trashdoctor-plan-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let present = dir.join("present.txt");
+        fs::write(&present, "twelve bytes").unwrap();
+        let held = fs::File::open(&present).unwrap();
+        let paths = vec![present.display().to_string(), dir.join("gone.txt").display().to_string()];
+
+        let plan = plan_delete(&paths);
+        let plan_path = dir.join("plan.json");
+        plan.save(&plan_path).unwrap();
+        let loaded = Plan::load(&plan_path).unwrap();
+        drop(held);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.operation, Operation::Delete);
+        assert_eq!(loaded.steps[0].bytes, 12);
+        assert_eq!(loaded.steps[0].problems, vec![Problem::InUse]);
+        assert_eq!(loaded.steps[1].problems, vec![Problem::Missing]);
+        assert_eq!(loaded.bytes_freed(), 0);
+    }
+
+    #[test]
+    fn test_execute_rechecks_steps() {
+        let dir = std::env::temp_dir().join(format!("trashdoctor-plan-recheck-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("opened-later.txt");
+        fs::write(&file, "contents").unwrap();
+
+        let plan = plan_delete(&[file.display().to_string()]);
+        assert!(plan.steps[0].problems.is_empty());
+
+        // Opened after planning, so the step must not go ahead
+        let held = fs::File::open(&file).unwrap();
+        let outcome = execute(&plan);
+        drop(held);
+        let still_there = file.exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(still_there);
+        assert!(matches!(&outcome.results[0].1, Err(FileActionError::Other(msg)) if msg.contains("in use")));
+    }
+}
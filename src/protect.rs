@@ -0,0 +1,132 @@
+//! Paths TrashDoctor never deletes, archives, trashes or links, whatever a
+//! rule or a user's selection says.
+//!
+//! The built-in list covers the operating system and the home folder's keys
+//! and credentials. More can be added to `~/.trashdoctor/protected`, one path
+//! per line; `~` is the home folder and lines starting with `#` are comments.
+//! The actions check every target against the list themselves, so no caller
+//! can get around it.
+
+use crate::actions::{data_dir, home_dir, FileActionError};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SYSTEM_PATHS: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/lib", "/lib32", "/lib64", "/libx32",
+    "/opt", "/proc", "/run", "/sbin", "/snap", "/sys", "/usr", "/var/lib",
+];
+
+const HOME_PATHS: &[&str] = &["~/.ssh", "~/.gnupg", "~/.pki", "~/.password-store", "~/.trashdoctor"];
+
+/// The user's additions to the built-in list.
+pub fn protected_file() -> PathBuf {
+    data_dir().join("protected")
+}
+
+/// The protected paths, built-in and the user's, loaded once so a scan or
+/// batch can check many files against them.
+#[derive(Clone, Debug)]
+pub struct ProtectedPaths {
+    paths: Vec<PathBuf>,
+}
+
+impl ProtectedPaths {
+    /// The built-in list plus [`protected_file`], if there is one.
+    pub fn load() -> Self {
+        let user = fs::read_to_string(protected_file()).unwrap_or_default();
+        let listed = SYSTEM_PATHS.iter()
+            .chain(HOME_PATHS)
+            .copied()
+            .chain(user.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')));
+
+        // Keep both spellings of symlinked entries, such as /lib on
+        // merged-/usr systems, so paths match whichever way they are written
+        let mut paths = Vec::new();
+        for entry in listed {
+            let path = expand_home(entry);
+            if let Ok(real) = fs::canonicalize(&path) {
+                if real != path {
+                    paths.push(real);
+                }
+            }
+            paths.push(path);
+        }
+        Self { paths }
+    }
+
+    /// Every protected path, as listed.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// The protected path `path` is, or is under, comparing the path as
+    /// written.
+    pub fn covering(&self, path: &Path) -> Option<&Path> {
+        let path = std::path::absolute(path).ok()?;
+        self.paths.iter()
+            .find(|protected| path.starts_with(protected))
+            .map(PathBuf::as_path)
+    }
+
+    /// The protected path inside `path`, if it is a directory holding one.
+    pub fn within(&self, path: &Path) -> Option<&Path> {
+        let path = std::path::absolute(path).ok()?;
+        self.paths.iter()
+            .find(|protected| protected.starts_with(&path))
+            .map(PathBuf::as_path)
+    }
+
+    /// Refuses `path` if it is protected, or is a directory with something
+    /// protected inside. Symlinked directories on the way are resolved, but
+    /// a symlink at the end is judged as itself, since that is what an
+    /// action removes.
+    pub fn check(&self, path: &Path) -> Result<(), FileActionError> {
+        let resolved = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => {
+                let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+                fs::canonicalize(parent).map(|dir| dir.join(name)).unwrap_or_else(|_| path.to_path_buf())
+            }
+            _ => path.to_path_buf(),
+        };
+
+        let found = self.covering(path)
+            .or_else(|| self.covering(&resolved))
+            .or_else(|| self.within(path))
+            .or_else(|| self.within(&resolved));
+        match found {
+            Some(protected) => Err(FileActionError::Protected(protected.to_path_buf())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Refuses `path` if it is protected, reading the list afresh.
+pub fn check(path: &str) -> Result<(), FileActionError> {
+    ProtectedPaths::load().check(Path::new(path))
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some(rest) => home_dir().join(rest.trim_start_matches('/')),
+        None => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protected_paths() {
+        let protected = ProtectedPaths { paths: vec![PathBuf::from("/etc"), PathBuf::from("/home/me/.ssh")] };
+
+        assert_eq!(protected.covering(Path::new("/etc/passwd")), Some(Path::new("/etc")));
+        assert_eq!(protected.covering(Path::new("/home/me/.ssh")), Some(Path::new("/home/me/.ssh")));
+        assert_eq!(protected.covering(Path::new("/home/me/.ssh_backup/id")), None);
+        assert_eq!(protected.covering(Path::new("/etcetera/a")), None);
+        assert!(matches!(protected.check(Path::new("/tmp/../etc/hosts")), Err(FileActionError::Protected(_))));
+        assert!(protected.check(Path::new("/tmp/a.txt")).is_ok());
+        assert_eq!(protected.within(Path::new("/home/me")), Some(Path::new("/home/me/.ssh")));
+        assert_eq!(protected.within(Path::new("/home/me/Downloads")), None);
+    }
+}
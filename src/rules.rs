@@ -1,26 +1,166 @@
+use crate::actions::{data_dir, format_file_size, FileActionError};
+use crate::expr;
 use crate::scanner::FileInfo;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-#[derive(Default, Clone, Debug)]
+/// Which files a rule matches. Fields left out of a rule file take their
+/// default, so files stay readable as new fields are added.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RuleConfig {
     pub max_age_days: u64,
+    /// Which timestamp `max_age_days` and `older:`/`newer:` are measured from.
+    pub age_basis: AgeBasis,
     pub min_size_mb: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_size_mb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub file_types: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub exclude_file_types: Option<Vec<String>>,
     pub include_hidden: bool,
     pub include_readonly: bool,
     pub include_executable: bool,
     pub custom_patterns: Vec<String>,
     pub exclude_patterns: Vec<String>,
+    /// Directory that archived matches are bundled into, e.g. a mount point
+    /// on another disk. `None` uses the default archive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_destination: Option<String>,
+    /// An [`expr`] expression files must also match, e.g.
+    /// `in:~/Downloads or name:*.iso`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expression: Option<String>,
 }
 
-#[derive(Clone, Debug)]
+impl RuleConfig {
+    /// Checks that the age and sizes can be counted in seconds and bytes.
+    pub fn check_limits(&self) -> Result<(), String> {
+        if days_to_secs(self.max_age_days).is_none() {
+            return Err(format!("a maximum age of {} days is too large", self.max_age_days));
+        }
+        match std::iter::once(self.min_size_mb).chain(self.max_size_mb).find(|&mb| mb_to_bytes(mb).is_none()) {
+            Some(mb) => Err(format!("a size of {} MB is too large", mb)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Days as seconds, or `None` when that does not fit.
+pub fn days_to_secs(days: u64) -> Option<u64> {
+    days.checked_mul(86400)
+}
+
+/// Megabytes as bytes, or `None` when that does not fit.
+pub fn mb_to_bytes(mb: u64) -> Option<u64> {
+    mb.checked_mul(1024 * 1024)
+}
+
+/// Which of a file's timestamps its age is counted from.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgeBasis {
+    /// Last read (atime). Barely moves on noatime and relatime mounts.
+    #[default]
+    Access,
+    /// Last change to the contents (mtime).
+    Modify,
+    /// Last change to the contents or metadata, such as a rename or chmod (ctime).
+    Change,
+    /// Creation (statx btime). Filesystems that don't record it fall back to
+    /// the modification time.
+    Birth,
+    /// The most recent of them, so a file is only old if nothing touched it.
+    Newest,
+    /// The earliest of them.
+    Oldest,
+}
+
+impl AgeBasis {
+    pub const ALL: [AgeBasis; 6] = [
+        AgeBasis::Access,
+        AgeBasis::Modify,
+        AgeBasis::Change,
+        AgeBasis::Birth,
+        AgeBasis::Newest,
+        AgeBasis::Oldest,
+    ];
+
+    /// The file's timestamp for this basis, in seconds since the epoch.
+    pub fn timestamp(self, file: &FileInfo) -> u64 {
+        let all = [
+            Some(file.last_access_secs),
+            Some(file.last_modified_secs),
+            Some(file.last_changed_secs),
+            file.created_secs,
+        ];
+        match self {
+            AgeBasis::Access => file.last_access_secs,
+            AgeBasis::Modify => file.last_modified_secs,
+            AgeBasis::Change => file.last_changed_secs,
+            AgeBasis::Birth => file.created_secs.unwrap_or(file.last_modified_secs),
+            AgeBasis::Newest => all.into_iter().flatten().max().unwrap_or_default(),
+            AgeBasis::Oldest => all.into_iter().flatten().min().unwrap_or_default(),
+        }
+    }
+
+    /// Seconds between the file's timestamp and `now_secs`.
+    pub fn age_secs(self, file: &FileInfo, now_secs: u64) -> u64 {
+        now_secs.saturating_sub(self.timestamp(file))
+    }
+
+    /// Name used on the command line and in rule files.
+    pub fn name(self) -> &'static str {
+        match self {
+            AgeBasis::Access => "access",
+            AgeBasis::Modify => "modify",
+            AgeBasis::Change => "change",
+            AgeBasis::Birth => "birth",
+            AgeBasis::Newest => "newest",
+            AgeBasis::Oldest => "oldest",
+        }
+    }
+}
+
+impl std::str::FromStr for AgeBasis {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        AgeBasis::ALL.into_iter()
+            .find(|basis| basis.name() == name.to_lowercase())
+            .ok_or_else(|| format!("unknown age basis '{}' (use access, modify, change, birth, newest or oldest)", name))
+    }
+}
+
+impl std::fmt::Display for AgeBasis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            AgeBasis::Access => "last accessed",
+            AgeBasis::Modify => "last modified",
+            AgeBasis::Change => "last changed",
+            AgeBasis::Birth => "created",
+            AgeBasis::Newest => "newest timestamp",
+            AgeBasis::Oldest => "oldest timestamp",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SmartRule {
     pub name: String,
+    #[serde(default)]
     pub description: String,
-    pub config: RuleConfig,
+    #[serde(default = "default_priority")]
     pub priority: u8,
+    pub config: RuleConfig,
+}
+
+fn default_priority() -> u8 {
+    5
 }
 
 impl SmartRule {
@@ -34,18 +174,163 @@ impl SmartRule {
     }
 }
 
+/// Where saved rules live, one `.toml` or `.json` file per rule. Copying a
+/// file between machines shares the rule.
+pub fn rules_dir() -> PathBuf {
+    data_dir().join("rules")
+}
+
+/// Reads a rule file, TOML or JSON by extension. A rule whose age or sizes
+/// are out of range is refused like any other unreadable file.
+pub fn load_rule_file(path: &Path) -> Result<SmartRule, FileActionError> {
+    let text = fs::read_to_string(path)?;
+    let parsed = match RuleFormat::of(path) {
+        Some(RuleFormat::Toml) => toml::from_str(&text).map_err(|e| e.to_string()),
+        Some(RuleFormat::Json) => serde_json::from_str(&text).map_err(|e| e.to_string()),
+        None => Err("rule files must end in .toml or .json".to_string()),
+    };
+    parsed.and_then(|rule: SmartRule| rule.config.check_limits().map(|_| rule))
+        .map_err(|e| FileActionError::Other(format!("{}: {}", path.display(), e)))
+}
+
+/// Writes a rule file, TOML or JSON by extension.
+pub fn save_rule_file(rule: &SmartRule, path: &Path) -> Result<(), FileActionError> {
+    let text = match RuleFormat::of(path) {
+        Some(RuleFormat::Toml) => toml::to_string_pretty(rule).map_err(|e| FileActionError::Other(e.to_string()))?,
+        Some(RuleFormat::Json) => serde_json::to_string_pretty(rule).map_err(|e| FileActionError::Other(e.to_string()))?,
+        None => return Err(FileActionError::Other("rule files must end in .toml or .json".to_string())),
+    };
+
+    // Written beside the target and renamed in, so a crash never leaves
+    // half a rule behind. The suffix keeps the full name, so x.toml and
+    // x.json never share a temporary file.
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    fs::write(&temp, text)?;
+    fs::rename(&temp, path).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        e.into()
+    })
+}
+
+/// Every rule in [`rules_dir`], sorted by name, plus the files that could
+/// not be read so a bad file is reported instead of hiding the rest.
+pub fn load_saved_rules() -> (Vec<SmartRule>, Vec<FileActionError>) {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+
+    let Ok(entries) = fs::read_dir(rules_dir()) else {
+        return (rules, errors);
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if RuleFormat::of(&path).is_none() {
+            continue;
+        }
+        match load_rule_file(&path) {
+            Ok(rule) => rules.push(rule),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    rules.sort_by_key(|rule| rule.name.to_lowercase());
+    (rules, errors)
+}
+
+/// Saves a rule into [`rules_dir`] as TOML, replacing any saved rule of the
+/// same name. Returns the file written.
+pub fn save_rule(rule: &SmartRule) -> Result<PathBuf, FileActionError> {
+    if rule.name.trim().is_empty() {
+        return Err(FileActionError::Other("A rule needs a name".to_string()));
+    }
+    let dir = rules_dir();
+    fs::create_dir_all(&dir)?;
+
+    let path = dir.join(format!("{}.toml", rule_file_stem(&rule.name)));
+    save_rule_file(rule, &path)?;
+
+    // The same rule may have arrived as JSON or under another file name
+    for other in saved_rule_files(&rule.name) {
+        if other != path {
+            let _ = fs::remove_file(other);
+        }
+    }
+    Ok(path)
+}
+
+/// Removes a saved rule by name.
+pub fn delete_saved_rule(name: &str) -> Result<(), FileActionError> {
+    let files = saved_rule_files(name);
+    if files.is_empty() {
+        return Err(FileActionError::FileNotFound);
+    }
+    for file in files {
+        fs::remove_file(file)?;
+    }
+    Ok(())
+}
+
+/// Looks a rule up by name, case-insensitively: saved rules first, so a
+/// saved rule can shadow a predefined one, then the predefined rules.
+pub fn find_rule(name: &str) -> Option<SmartRule> {
+    let (saved, _) = load_saved_rules();
+    saved.into_iter()
+        .chain(get_predefined_rules())
+        .find(|rule| rule.name.eq_ignore_ascii_case(name))
+}
+
+fn saved_rule_files(name: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(rules_dir()) else {
+        return Vec::new();
+    };
+    entries.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| RuleFormat::of(path).is_some())
+        .filter(|path| load_rule_file(path).is_ok_and(|rule| rule.name.eq_ignore_ascii_case(name)))
+        .collect()
+}
+
+// "Old ISOs (team)" -> "old-isos-team"
+fn rule_file_stem(name: &str) -> String {
+    let stem: String = name.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    let stem = stem.split('-').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-");
+    if stem.is_empty() { "rule".to_string() } else { stem }
+}
+
+enum RuleFormat {
+    Toml,
+    Json,
+}
+
+impl RuleFormat {
+    fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "toml" => Some(RuleFormat::Toml),
+            "json" => Some(RuleFormat::Json),
+            _ => None,
+        }
+    }
+}
+
 pub fn apply_rules(files: &[FileInfo], rule: &RuleConfig) -> Vec<FileInfo> {
     let mut result = Vec::new();
-    let max_age_secs = rule.max_age_days * 86400;
-    let min_size_bytes = rule.min_size_mb * 1024 * 1024;
-    let max_size_bytes = rule.max_size_mb.map(|mb| mb * 1024 * 1024);
-    let now_secs = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
+    let limits = Limits::new(rule);
+    
+    // An expression that does not parse matches nothing rather than
+    // everything; callers check it with expr::parse to report the error
+    let expression = match rule.expression.as_deref().map(str::trim).filter(|text| !text.is_empty()) {
+        Some(text) => match expr::parse(text) {
+            Ok(expression) => Some(expression),
+            Err(_) => return result,
+        },
+        None => None,
+    };
     
     for file in files {
-        if !matches_rule(file, rule, max_age_secs, min_size_bytes, max_size_bytes, now_secs) {
+        if !matches_rule(file, rule, &limits, expression.as_ref(), &mut Trace::default()) {
             continue;
         }
         
@@ -55,56 +340,164 @@ pub fn apply_rules(files: &[FileInfo], rule: &RuleConfig) -> Vec<FileInfo> {
     result
 }
 
-fn matches_rule(
-    file: &FileInfo,
-    rule: &RuleConfig,
+/// One check a rule made on a file, as reported by [`explain`].
+#[derive(Clone, Debug)]
+pub struct Check {
+    /// What was checked, e.g. "min size" or an expression predicate.
+    pub name: String,
+    /// The file's value.
+    pub observed: String,
+    /// What the rule asks for.
+    pub expected: String,
+    pub passed: bool,
+    /// Nesting inside the rule expression. Only depth 0 checks decide the
+    /// match directly; deeper ones feed into the `and`/`or`/`not` above them.
+    pub depth: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Explanation {
+    pub matched: bool,
+    pub checks: Vec<Check>,
+}
+
+/// Runs every check of `rule` on `file`, without stopping at the first
+/// failure, so it is clear which ones decided the result.
+pub fn explain(file: &FileInfo, rule: &RuleConfig) -> Explanation {
+    let limits = Limits::new(rule);
+    let mut checks = Vec::new();
+    let mut trace = Trace { checks: Some(&mut checks), failed: false };
+
+    let matched = match rule.expression.as_deref().map(str::trim).filter(|text| !text.is_empty()) {
+        Some(text) => match expr::parse(text) {
+            Ok(expression) => matches_rule(file, rule, &limits, Some(&expression), &mut trace),
+            Err(err) => {
+                matches_rule(file, rule, &limits, None, &mut trace);
+                trace.check(false, || ("expression".to_string(), format!("invalid: {}", err), text.to_string()));
+                false
+            }
+        },
+        None => matches_rule(file, rule, &limits, None, &mut trace),
+    };
+
+    Explanation { matched, checks }
+}
+
+// A rule's thresholds, worked out once per rule rather than once per file
+struct Limits {
     max_age_secs: u64,
     min_size_bytes: u64,
     max_size_bytes: Option<u64>,
     now_secs: u64,
+}
+
+impl Limits {
+    // Out-of-range values saturate rather than wrap to a small threshold
+    fn new(rule: &RuleConfig) -> Self {
+        Self {
+            max_age_secs: days_to_secs(rule.max_age_days).unwrap_or(u64::MAX),
+            min_size_bytes: mb_to_bytes(rule.min_size_mb).unwrap_or(u64::MAX),
+            max_size_bytes: rule.max_size_mb.map(|mb| mb_to_bytes(mb).unwrap_or(u64::MAX)),
+            now_secs: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+}
+
+// Collects checks for explain. Without somewhere to record them,
+// evaluation stops at the first failure.
+#[derive(Default)]
+struct Trace<'a> {
+    checks: Option<&'a mut Vec<Check>>,
+    failed: bool,
+}
+
+impl Trace<'_> {
+    // Returns whether evaluation should go on
+    fn check(&mut self, passed: bool, describe: impl FnOnce() -> (String, String, String)) -> bool {
+        self.failed |= !passed;
+        match &mut self.checks {
+            Some(checks) => {
+                let (name, observed, expected) = describe();
+                checks.push(Check { name, observed, expected, passed, depth: 0 });
+                true
+            }
+            None => passed,
+        }
+    }
+}
+
+fn matches_rule(
+    file: &FileInfo,
+    rule: &RuleConfig,
+    limits: &Limits,
+    expression: Option<&expr::Expr>,
+    trace: &mut Trace,
 ) -> bool {
-    // Age check - file.last_access_secs is a timestamp, so we need to calculate age
-    let file_age_secs = now_secs.saturating_sub(file.last_access_secs);
-    if file_age_secs < max_age_secs {
+    let yes_no = |value: bool| if value { "yes" } else { "no" }.to_string();
+    
+    // Age check, counted from the rule's choice of timestamp
+    let file_age_secs = rule.age_basis.age_secs(file, limits.now_secs);
+    if !trace.check(file_age_secs >= limits.max_age_secs, || {
+        (format!("age ({})", rule.age_basis), format_age(file_age_secs), format!("at least {}", format_age(limits.max_age_secs)))
+    }) {
         return false;
     }
     
     // Size checks
-    if file.size < min_size_bytes {
+    if !trace.check(file.size >= limits.min_size_bytes, || {
+        ("min size".to_string(), format_file_size(file.size), format!("at least {}", format_file_size(limits.min_size_bytes)))
+    }) {
         return false;
     }
     
-    if let Some(max_size) = max_size_bytes {
-        if file.size > max_size {
+    if let Some(max_size) = limits.max_size_bytes {
+        if !trace.check(file.size <= max_size, || {
+            ("max size".to_string(), format_file_size(file.size), format!("at most {}", format_file_size(max_size)))
+        }) {
             return false;
         }
     }
     
     // Hidden files check
-    if file.is_hidden && !rule.include_hidden {
+    if file.is_hidden && !trace.check(rule.include_hidden, || {
+        ("hidden".to_string(), yes_no(true), "hidden files allowed".to_string())
+    }) {
         return false;
     }
     
     // Readonly files check
-    if file.is_readonly && !rule.include_readonly {
+    if file.is_readonly && !trace.check(rule.include_readonly, || {
+        ("read-only".to_string(), yes_no(true), "read-only files allowed".to_string())
+    }) {
         return false;
     }
     
     // Executable files check
-    if file.is_executable && !rule.include_executable {
+    if file.is_executable && !trace.check(rule.include_executable, || {
+        ("executable".to_string(), yes_no(true), "executables allowed".to_string())
+    }) {
         return false;
     }
     
     // File type inclusion filter
     if let Some(ref include_types) = rule.file_types {
-        if !include_types.iter().any(|t| file.file_type.to_lowercase().contains(&t.to_lowercase())) {
+        let included = include_types.iter().any(|t| file.file_type.to_lowercase().contains(&t.to_lowercase()));
+        if !trace.check(included, || {
+            ("file type".to_string(), file.file_type.clone(), format!("one of {}", include_types.join(", ")))
+        }) {
             return false;
         }
     }
     
     // File type exclusion filter
     if let Some(ref exclude_types) = rule.exclude_file_types {
-        if exclude_types.iter().any(|t| file.file_type.to_lowercase().contains(&t.to_lowercase())) {
+        let excluded = exclude_types.iter().any(|t| file.file_type.to_lowercase().contains(&t.to_lowercase()));
+        if !trace.check(!excluded, || {
+            ("excluded type".to_string(), file.file_type.clone(), format!("none of {}", exclude_types.join(", ")))
+        }) {
             return false;
         }
     }
@@ -114,25 +507,62 @@ fn matches_rule(
         let matches_pattern = rule.custom_patterns.iter().any(|pattern| {
             pattern_matches(&file.path, pattern)
         });
-        if !matches_pattern {
+        if !trace.check(matches_pattern, || {
+            ("match pattern".to_string(), file.path.clone(), format!("matching {}", rule.custom_patterns.join(" or ")))
+        }) {
             return false;
         }
     }
     
     // Exclude pattern matching
     if !rule.exclude_patterns.is_empty() {
-        let matches_exclude = rule.exclude_patterns.iter().any(|pattern| {
+        let matched_exclude = rule.exclude_patterns.iter().find(|pattern| {
             pattern_matches(&file.path, pattern)
         });
-        if matches_exclude {
+        if !trace.check(matched_exclude.is_none(), || {
+            let observed = match matched_exclude {
+                Some(pattern) => format!("{} (matches {})", file.path, pattern),
+                None => file.path.clone(),
+            };
+            ("exclude pattern".to_string(), observed, format!("not matching {}", rule.exclude_patterns.join(" or ")))
+        }) {
             return false;
         }
     }
     
-    true
+    // Rule expression, with each predicate traced under it
+    if let Some(expression) = expression {
+        let passed = match &mut trace.checks {
+            Some(checks) => {
+                let at = checks.len();
+                let passed = expression.trace(file, limits.now_secs, rule.age_basis, 1, checks);
+                checks.insert(at, Check {
+                    name: "expression".to_string(),
+                    observed: if passed { "matched" } else { "did not match" }.to_string(),
+                    expected: rule.expression.clone().unwrap_or_default(),
+                    passed,
+                    depth: 0,
+                });
+                passed
+            }
+            None => expression.matches(file, limits.now_secs, rule.age_basis),
+        };
+        trace.failed |= !passed;
+    }
+    
+    !trace.failed
+}
+
+// "400 days", or hours for anything under a day
+pub(crate) fn format_age(secs: u64) -> String {
+    match secs / 86400 {
+        0 => format!("{} hours", secs / 3600),
+        1 => "1 day".to_string(),
+        days => format!("{} days", days),
+    }
 }
 
-fn pattern_matches(path: &str, pattern: &str) -> bool {
+pub(crate) fn pattern_matches(path: &str, pattern: &str) -> bool {
     // Simple pattern matching with wildcards
     if pattern.contains('*') {
         wildcard_match(pattern, path)
@@ -392,6 +822,99 @@ mod tests {
         assert!(!wildcard_match("*.jpg", "test.txt"));
     }
     
+    #[test]
+    fn test_rule_file_round_trip() {
+        let dir = std::env::temp_dir().join(format!("trashdoctor-rules-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let rule = get_predefined_rules().remove(0);
+        for file in ["rule.toml", "rule.json"] {
+            save_rule_file(&rule, &dir.join(file)).unwrap();
+            let loaded = load_rule_file(&dir.join(file)).unwrap();
+            assert_eq!(loaded.name, rule.name);
+            assert_eq!(loaded.config.custom_patterns, rule.config.custom_patterns);
+            assert_eq!(loaded.config.max_size_mb, None);
+        }
+
+        // Missing fields fall back to their defaults
+        fs::write(dir.join("short.toml"), "name = \"ISOs\"\n[config]\nmin_size_mb = 700\n").unwrap();
+        let short = load_rule_file(&dir.join("short.toml")).unwrap();
+
+        // Out-of-range limits are reported rather than matching everything
+        fs::write(dir.join("huge.toml"), "name = \"Huge\"\n[config]\nmax_age_days = 300000000000000\n").unwrap();
+        let huge = load_rule_file(&dir.join("huge.toml"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(huge.is_err());
+        assert_eq!(short.priority, 5);
+        assert_eq!(short.config.min_size_mb, 700);
+        assert!(!short.config.include_hidden);
+
+        assert_eq!(rule_file_stem("Old ISOs (team)"), "old-isos-team");
+    }
+
+    #[test]
+    fn test_explain() {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let file = FileInfo {
+            path: "/home/user/.cache/old.tmp".to_string(),
+            size: 5 * 1024 * 1024,
+            last_accessed: String::new(),
+            last_access_secs: now - 90 * 86400,
+            last_modified: String::new(),
+            last_modified_secs: now - 90 * 86400,
+            last_changed_secs: now - 90 * 86400,
+            created_secs: None,
+            atime_mode: Default::default(),
+            file_type: "tmp".to_string(),
+            is_hidden: true,
+            is_readonly: false,
+            is_executable: false,
+        };
+        let rule = RuleConfig {
+            max_age_days: 30,
+            min_size_mb: 10,
+            expression: Some("ext:tmp or larger:1G".to_string()),
+            ..Default::default()
+        };
+
+        let explanation = explain(&file, &rule);
+        assert_eq!(explanation.matched, !apply_rules(std::slice::from_ref(&file), &rule).is_empty());
+        let failed: Vec<&str> = explanation.checks.iter()
+            .filter(|check| !check.passed)
+            .map(|check| check.name.as_str())
+            .collect();
+        assert_eq!(failed, vec!["min size", "hidden", "larger:1G"]);
+    }
+
+    #[test]
+    fn test_limits_do_not_wrap() {
+        let huge = RuleConfig { max_age_days: 300_000_000_000_000, ..Default::default() };
+        assert!(huge.check_limits().is_err());
+        assert!(RuleConfig { max_size_mb: Some(u64::MAX), ..Default::default() }.check_limits().is_err());
+        assert!(RuleConfig { max_age_days: 30, min_size_mb: 100, ..Default::default() }.check_limits().is_ok());
+
+        // An age that does not fit saturates, so nothing is old enough
+        let file = FileInfo {
+            path: "/tmp/a.txt".to_string(),
+            size: 1,
+            last_accessed: String::new(),
+            last_access_secs: 0,
+            last_modified: String::new(),
+            last_modified_secs: 0,
+            last_changed_secs: 0,
+            created_secs: None,
+            atime_mode: Default::default(),
+            file_type: "txt".to_string(),
+            is_hidden: false,
+            is_readonly: false,
+            is_executable: false,
+        };
+        assert!(apply_rules(&[file], &huge).is_empty());
+    }
+
     #[test]
     fn test_pattern_matches() {
         assert!(pattern_matches("/home/user/Downloads/file.txt", "*/Downloads/*"));
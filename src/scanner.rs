@@ -1,11 +1,18 @@
-use walkdir::WalkDir;
+use crate::mounts::{AtimeMode, MountTable};
+use crate::protect::ProtectedPaths;
 use std::fs;
 use chrono::{DateTime, Local};
 use std::time::SystemTime;
 use std::path::Path;
 use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::AtomicBool;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+// Size of the head and tail blocks compared before hashing whole files
+const HASH_BLOCK_SIZE: u64 = 4096;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FileInfo {
     pub path: String,
     pub size: u64,
@@ -13,6 +20,13 @@ pub struct FileInfo {
     pub last_access_secs: u64,
     pub last_modified: String,
     pub last_modified_secs: u64,
+    /// Last status change (ctime), in seconds since the epoch; the
+    /// modification time on platforms without one.
+    pub last_changed_secs: u64,
+    /// Creation time, where the filesystem records one.
+    pub created_secs: Option<u64>,
+    /// How the file's filesystem keeps `last_access_secs` up to date.
+    pub atime_mode: AtimeMode,
     pub file_type: String,
     pub is_hidden: bool,
     pub is_readonly: bool,
@@ -22,11 +36,39 @@ pub struct FileInfo {
 #[derive(Clone, Debug)]
 pub struct ScanOptions {
     pub include_hidden: bool,
+    /// Walk into protected paths such as /usr or ~/.ssh. Their files are
+    /// listed but the actions still refuse them.
     pub include_system: bool,
     pub max_depth: Option<usize>,
     pub follow_symlinks: bool,
     pub file_extensions: Option<Vec<String>>,
     pub exclude_patterns: Vec<String>,
+    /// Threads that read directories in parallel; 0 uses one per CPU.
+    pub threads: usize,
+    /// Return files in the order a single-threaded walk would find them,
+    /// rather than the order the threads happen to finish in.
+    pub ordered: bool,
+}
+
+/// Running totals reported while a scan is in progress.
+#[derive(Clone, Debug, Default)]
+pub struct ScanProgress {
+    pub files_scanned: u64,
+    pub bytes_seen: u64,
+    pub entries_done: usize,
+    pub entries_total: usize,
+    pub current_path: String,
+}
+
+impl ScanProgress {
+    /// Fraction of the top-level entries visited so far, from 0.0 to 1.0.
+    pub fn fraction(&self) -> f32 {
+        if self.entries_total == 0 {
+            0.0
+        } else {
+            (self.entries_done as f32 / self.entries_total as f32).min(1.0)
+        }
+    }
 }
 
 impl Default for ScanOptions {
@@ -43,6 +85,8 @@ impl Default for ScanOptions {
                 "*/.git/*".to_string(),
                 "*/node_modules/*".to_string(),
             ],
+            threads: 0,
+            ordered: true,
         }
     }
 }
@@ -52,72 +96,108 @@ pub fn scan_folder(folder: &str) -> Vec<FileInfo> {
 }
 
 pub fn scan_folder_with_options(folder: &str, options: &ScanOptions) -> Vec<FileInfo> {
-    let mut files = Vec::new();
-    
-    let mut walker = WalkDir::new(folder).follow_links(options.follow_symlinks);
+    scan_folder_with_progress(folder, options, &AtomicBool::new(false), |_| {})
+}
+
+/// Walks `folder` like [`scan_folder_with_options`], reporting progress to
+/// `on_progress` as it goes. Setting `cancel` stops the walk early; the files
+/// found up to that point are still returned.
+pub fn scan_folder_with_progress<F>(
+    folder: &str,
+    options: &ScanOptions,
+    cancel: &AtomicBool,
+    on_progress: F,
+) -> Vec<FileInfo>
+where
+    F: FnMut(&ScanProgress),
+{
+    crate::walker::walk(folder, options, cancel, on_progress, None)
+}
+
+/// Why a scan with `options` would leave `path` out, or `None` if it would
+/// be included.
+pub fn skip_reason(path: &Path, options: &ScanOptions) -> Option<String> {
+    if !options.include_system {
+        if let Some(protected) = ProtectedPaths::load().covering(path) {
+            return Some(format!("{} is protected and not scanned", protected.display()));
+        }
+    }
+    filter_reason(path, options)
+}
+
+// The checks made on each file found, after protected directories are pruned
+pub(crate) fn filter_reason(path: &Path, options: &ScanOptions) -> Option<String> {
+    // Skip hidden files if not requested
+    if !options.include_hidden && is_hidden_file(path) {
+        return Some("hidden files are not scanned".to_string());
+    }
     
-    if let Some(max_depth) = options.max_depth {
-        walker = walker.max_depth(max_depth);
+    // Check exclude patterns
+    if let Some(pattern) = options.exclude_patterns.iter().find(|p| should_exclude_file(path, std::slice::from_ref(p))) {
+        return Some(format!("skipped by the scan pattern {}", pattern));
     }
     
-    for entry in walker.into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            let path = entry.path();
-            
-            // Skip hidden files if not requested
-            if !options.include_hidden && is_hidden_file(path) {
-                continue;
-            }
-            
-            // Check exclude patterns
-            if should_exclude_file(path, &options.exclude_patterns) {
-                continue;
-            }
-            
-            // Check file extension filter
-            if let Some(ref extensions) = options.file_extensions {
-                if let Some(ext) = path.extension() {
-                    let ext_str = ext.to_str().unwrap_or("").to_lowercase();
-                    if !extensions.iter().any(|e| e.to_lowercase() == ext_str) {
-                        continue;
-                    }
-                } else if !extensions.is_empty() {
-                    continue;
-                }
-            }
-            
-            if let Ok(metadata) = fs::metadata(path) {
-                let accessed = metadata.accessed().unwrap_or(SystemTime::now());
-                let modified = metadata.modified().unwrap_or(SystemTime::now());
-                
-                let access_datetime: DateTime<Local> = accessed.into();
-                let modified_datetime: DateTime<Local> = modified.into();
-                
-                let access_age_secs = accessed.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
-                let modified_age_secs = modified.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
-                
-                let file_type = get_file_type_from_path(path);
-                let is_hidden = is_hidden_file(path);
-                let is_readonly = metadata.permissions().readonly();
-                let is_executable = is_executable_file(&metadata);
-                
-                files.push(FileInfo {
-                    path: path.display().to_string(),
-                    size: metadata.len(),
-                    last_accessed: access_datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
-                    last_access_secs: access_age_secs,
-                    last_modified: modified_datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
-                    last_modified_secs: modified_age_secs,
-                    file_type,
-                    is_hidden,
-                    is_readonly,
-                    is_executable,
-                });
-            }
+    // Check file extension filter
+    if let Some(ref extensions) = options.file_extensions {
+        let allowed = match path.extension() {
+            Some(ext) => extensions.iter().any(|e| e.to_lowercase() == ext.to_string_lossy().to_lowercase()),
+            None => extensions.is_empty(),
+        };
+        if !allowed {
+            return Some(format!("only {} files are scanned", extensions.join(", ")));
         }
     }
     
-    files
+    None
+}
+
+/// Reads one file the way a scan would.
+pub fn file_info(path: &Path) -> io::Result<FileInfo> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a regular file"));
+    }
+    Ok(file_info_from_metadata(path, &metadata, &mut MountTable::load()))
+}
+
+pub(crate) fn file_info_from_metadata(path: &Path, metadata: &fs::Metadata, mounts: &mut MountTable) -> FileInfo {
+    let accessed = metadata.accessed().unwrap_or(SystemTime::now());
+    let modified = metadata.modified().unwrap_or(SystemTime::now());
+    
+    let access_datetime: DateTime<Local> = accessed.into();
+    let modified_datetime: DateTime<Local> = modified.into();
+    
+    let access_age_secs = accessed.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+    let modified_age_secs = modified.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+    // std reads the birth time with statx where the kernel and filesystem support it
+    let created_secs = metadata.created()
+        .ok()
+        .and_then(|created| created.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs());
+    #[cfg(unix)]
+    let (changed_secs, dev) = {
+        use std::os::unix::fs::MetadataExt;
+        (u64::try_from(metadata.ctime()).unwrap_or_default(), metadata.dev())
+    };
+    // No status change time off Unix; the last modification is the nearest
+    #[cfg(not(unix))]
+    let (changed_secs, dev) = (modified_age_secs, 0);
+    
+    FileInfo {
+        path: path.display().to_string(),
+        size: metadata.len(),
+        last_accessed: access_datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+        last_access_secs: access_age_secs,
+        last_modified: modified_datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+        last_modified_secs: modified_age_secs,
+        last_changed_secs: changed_secs,
+        created_secs,
+        atime_mode: mounts.atime_mode(path, dev),
+        file_type: get_file_type_from_path(path),
+        is_hidden: is_hidden_file(path),
+        is_readonly: metadata.permissions().readonly(),
+        is_executable: is_executable_file(metadata),
+    }
 }
 
 pub fn get_file_type_statistics(files: &[FileInfo]) -> HashMap<String, (usize, u64)> {
@@ -144,17 +224,116 @@ pub fn get_oldest_files(files: &[FileInfo], count: usize) -> Vec<&FileInfo> {
     sorted_files.into_iter().take(count).collect()
 }
 
-pub fn get_duplicate_files(files: &[FileInfo]) -> HashMap<u64, Vec<&FileInfo>> {
+/// Files whose contents are byte-for-byte identical.
+#[derive(Clone, Debug)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    pub hash: String,
+    pub files: Vec<FileInfo>,
+}
+
+/// Which copy of a duplicate group to keep.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeepPolicy {
+    Oldest,
+    Newest,
+    ShortestPath,
+    /// Keep the copy inside this directory, falling back to the oldest.
+    PreferDirectory(String),
+}
+
+impl DuplicateGroup {
+    /// Bytes freed by keeping only one copy of the group.
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.size * (self.files.len() as u64).saturating_sub(1)
+    }
+    
+    /// Index of the copy the policy keeps.
+    pub fn keeper(&self, policy: &KeepPolicy) -> usize {
+        let indices = 0..self.files.len();
+        let oldest = || indices.clone().min_by_key(|&i| self.files[i].last_modified_secs);
+        
+        let keeper = match policy {
+            KeepPolicy::Oldest => oldest(),
+            KeepPolicy::Newest => indices.clone().max_by_key(|&i| self.files[i].last_modified_secs),
+            KeepPolicy::ShortestPath => indices.clone()
+                .min_by(|&a, &b| {
+                    let (a, b) = (&self.files[a].path, &self.files[b].path);
+                    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+                }),
+            KeepPolicy::PreferDirectory(dir) => indices.clone()
+                .find(|&i| Path::new(&self.files[i].path).starts_with(dir))
+                .or_else(oldest),
+        };
+        
+        keeper.unwrap_or(0)
+    }
+    
+    /// Every copy except the one the policy keeps.
+    pub fn redundant_files(&self, policy: &KeepPolicy) -> Vec<&FileInfo> {
+        let keeper = self.keeper(policy);
+        self.files.iter()
+            .enumerate()
+            .filter(|(i, _)| *i != keeper)
+            .map(|(_, file)| file)
+            .collect()
+    }
+}
+
+/// Finds groups of files with identical content.
+///
+/// Candidates are narrowed in stages so that most files are never read in
+/// full: first by size, then by a hash of their head and tail blocks, and
+/// finally by a hash of the whole file. Empty files and extra hardlinks to
+/// the same inode are ignored since removing them frees no space. Groups are
+/// returned largest saving first. The hash is not collision-resistant; check
+/// copies with [`verify_duplicates`](crate::actions::verify_duplicates)
+/// before acting on them.
+pub fn get_duplicate_files(files: &[FileInfo]) -> Vec<DuplicateGroup> {
     let mut size_groups: HashMap<u64, Vec<&FileInfo>> = HashMap::new();
     
     for file in files {
-        size_groups.entry(file.size).or_insert_with(Vec::new).push(file);
+        if file.size > 0 {
+            size_groups.entry(file.size).or_default().push(file);
+        }
     }
     
-    // Filter to only groups with more than one file
-    size_groups.into_iter()
-        .filter(|(_, files)| files.len() > 1)
-        .collect()
+    let mut groups = Vec::new();
+    
+    for (size, candidates) in size_groups {
+        if candidates.len() < 2 {
+            continue;
+        }
+        
+        // Several names for one inode share their storage
+        let mut seen_inodes = std::collections::HashSet::new();
+        let candidates: Vec<&FileInfo> = candidates.into_iter()
+            .filter(|file| match file_identity(Path::new(&file.path)) {
+                Some(identity) => seen_inodes.insert(identity),
+                None => true,
+            })
+            .collect();
+        
+        for partial_group in group_by_hash(candidates, |path| partial_hash_file(path, size)) {
+            // Small files were read completely by the partial hash
+            let full_groups = if size <= 2 * HASH_BLOCK_SIZE {
+                vec![partial_group]
+            } else {
+                group_by_hash(partial_group.1, hash_file)
+            };
+            
+            for (hash, duplicates) in full_groups {
+                groups.push(DuplicateGroup {
+                    size,
+                    hash,
+                    files: duplicates.into_iter().cloned().collect(),
+                });
+            }
+        }
+    }
+    
+    groups.sort_by_key(|group| std::cmp::Reverse(group.reclaimable_bytes()));
+    groups
 }
 
 pub fn calculate_space_savings(files: &[FileInfo]) -> (u64, u64) {
@@ -162,17 +341,117 @@ pub fn calculate_space_savings(files: &[FileInfo]) -> (u64, u64) {
     let mut potential_savings = 0u64;
     let mut duplicate_count = 0u64;
     
-    for (size, duplicate_files) in duplicates {
-        if duplicate_files.len() > 1 {
-            // Keep one copy, remove the rest
-            potential_savings += size * (duplicate_files.len() - 1) as u64;
-            duplicate_count += (duplicate_files.len() - 1) as u64;
-        }
+    for group in duplicates {
+        // Keep one copy, remove the rest
+        potential_savings += group.reclaimable_bytes();
+        duplicate_count += (group.files.len() - 1) as u64;
     }
     
     (potential_savings, duplicate_count)
 }
 
+/// Hashes the full contents of a file, returned as a hex string.
+pub fn hash_file(path: &str) -> io::Result<String> {
+    hash_reader(&mut fs::File::open(path)?)
+}
+
+/// Hashes everything `reader` yields, the same way as [`hash_file`].
+pub fn hash_reader(reader: &mut dyn Read) -> io::Result<String> {
+    let mut hasher = ContentHasher::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    
+    Ok(hasher.finish_hex())
+}
+
+// Hashes the first and last block of a file, or all of it when it is small
+fn partial_hash_file(path: &str, size: u64) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = ContentHasher::new();
+    let mut buffer = vec![0u8; HASH_BLOCK_SIZE as usize];
+    
+    if size <= 2 * HASH_BLOCK_SIZE {
+        let mut contents = Vec::with_capacity(size as usize);
+        file.read_to_end(&mut contents)?;
+        hasher.update(&contents);
+    } else {
+        file.read_exact(&mut buffer)?;
+        hasher.update(&buffer);
+        file.seek(SeekFrom::End(-(HASH_BLOCK_SIZE as i64)))?;
+        file.read_exact(&mut buffer)?;
+        hasher.update(&buffer);
+    }
+    
+    Ok(hasher.finish_hex())
+}
+
+type HashGroup<'a> = (String, Vec<&'a FileInfo>);
+
+// Splits files by the given hash, keeping only groups with more than one
+// member. Files that cannot be read are left out.
+fn group_by_hash<'a, H>(files: Vec<&'a FileInfo>, hash: H) -> Vec<HashGroup<'a>>
+where
+    H: Fn(&str) -> io::Result<String>,
+{
+    let mut by_hash: HashMap<String, Vec<&FileInfo>> = HashMap::new();
+    
+    for file in files {
+        if let Ok(digest) = hash(&file.path) {
+            by_hash.entry(digest).or_default().push(file);
+        }
+    }
+    
+    by_hash.into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .collect()
+}
+
+// 128-bit FNV-1a; stable across runs and platforms, which matters once
+// hashes are written to disk. It is not collision-resistant, so duplicates
+// are compared byte for byte (actions::verify_duplicates) before a copy is
+// removed
+struct ContentHasher {
+    state: u128,
+}
+
+impl ContentHasher {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    
+    fn new() -> Self {
+        Self { state: Self::OFFSET_BASIS }
+    }
+    
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state ^= byte as u128;
+            self.state = self.state.wrapping_mul(Self::PRIME);
+        }
+    }
+    
+    fn finish_hex(&self) -> String {
+        format!("{:032x}", self.state)
+    }
+}
+
+#[cfg(unix)]
+fn file_identity(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|metadata| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
 fn get_file_type_from_path(path: &Path) -> String {
     match path.extension() {
         Some(ext) => {
@@ -299,6 +578,102 @@ mod tests {
         assert_eq!(get_file_type_from_path(Path::new("test")), "No Extension");
     }
     
+    #[test]
+    fn test_scan_cancelled() {
+        let cancel = AtomicBool::new(true);
+        let files = scan_folder_with_progress(env!("CARGO_MANIFEST_DIR"), &ScanOptions::default(), &cancel, |_| {});
+        assert!(files.is_empty());
+    }
+    
+    #[test]
+    fn test_parallel_walk_order() {
+        let dir = std::env::temp_dir().join(format!("trashdoctor-walk-{}", std::process::id()));
+        for sub in ["a/b/c", "a/d", "e", "f/g/h/i"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+            for name in ["1.txt", "2.txt", "3.txt"] {
+                fs::write(dir.join(sub).join(name), sub).unwrap();
+            }
+        }
+        fs::write(dir.join("top.txt"), "top").unwrap();
+        
+        let walked: Vec<String> = walkdir::WalkDir::new(&dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.path().display().to_string())
+            .collect();
+        let options = ScanOptions { threads: 4, ..ScanOptions::default() };
+        let scanned: Vec<String> = scan_folder_with_options(dir.to_str().unwrap(), &options)
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        let shallow = scan_folder_with_options(dir.to_str().unwrap(), &ScanOptions { max_depth: Some(2), ..options });
+        fs::remove_dir_all(&dir).unwrap();
+        
+        assert_eq!(scanned.len(), 13);
+        assert_eq!(scanned, walked);
+        assert_eq!(shallow.len(), 4);
+    }
+    
+    #[test]
+    fn test_duplicates_need_equal_content() {
+        let dir = std::env::temp_dir().join(format!("trashdoctor-dups-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let large: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+        let mut changed = large.clone();
+        changed[10_000] ^= 1;
+        
+        fs::write(dir.join("a.bin"), &large).unwrap();
+        fs::write(dir.join("b.bin"), &large).unwrap();
+        fs::write(dir.join("c.bin"), &changed).unwrap();
+        fs::write(dir.join("d.txt"), "same size").unwrap();
+        fs::write(dir.join("e.txt"), "also size").unwrap();
+        
+        let files = scan_folder(dir.to_str().unwrap());
+        let groups = get_duplicate_files(&files);
+        fs::remove_dir_all(&dir).unwrap();
+        
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files.len(), 2);
+        assert!(groups[0].files.iter().all(|f| !f.path.ends_with("c.bin")));
+        assert_eq!(groups[0].reclaimable_bytes(), large.len() as u64);
+    }
+    
+    #[test]
+    fn test_keep_policy() {
+        let file = |path: &str, modified: u64| FileInfo {
+            path: path.to_string(),
+            size: 10,
+            last_accessed: String::new(),
+            last_access_secs: 0,
+            last_modified: String::new(),
+            last_modified_secs: modified,
+            last_changed_secs: modified,
+            created_secs: None,
+            atime_mode: AtimeMode::Unknown,
+            file_type: "Text".to_string(),
+            is_hidden: false,
+            is_readonly: false,
+            is_executable: false,
+        };
+        let group = DuplicateGroup {
+            size: 10,
+            hash: String::new(),
+            files: vec![
+                file("/home/me/Downloads/report (1).txt", 300),
+                file("/home/me/report.txt", 100),
+                file("/home/me/keep/report.txt", 200),
+            ],
+        };
+        
+        assert_eq!(group.keeper(&KeepPolicy::Oldest), 1);
+        assert_eq!(group.keeper(&KeepPolicy::Newest), 0);
+        assert_eq!(group.keeper(&KeepPolicy::ShortestPath), 1);
+        assert_eq!(group.keeper(&KeepPolicy::PreferDirectory("/home/me/keep".to_string())), 2);
+        assert_eq!(group.keeper(&KeepPolicy::PreferDirectory("/elsewhere".to_string())), 1);
+        assert_eq!(group.redundant_files(&KeepPolicy::Oldest).len(), 2);
+    }
+    
     #[test]
     fn test_is_hidden_file() {
         assert!(is_hidden_file(Path::new(".hidden")));
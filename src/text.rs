@@ -0,0 +1,21 @@
+//! Small helpers for the messages the GUI and command line print.
+
+/// `word` with its first letter in upper case, for messages built from a verb.
+pub fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capitalize() {
+        assert_eq!(capitalize("archived"), "Archived");
+        assert_eq!(capitalize(""), "");
+    }
+}
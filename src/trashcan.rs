@@ -0,0 +1,596 @@
+//! The FreeDesktop.org trash, as used by Nautilus, Dolphin and friends.
+//!
+//! Files on the home volume go to `$XDG_DATA_HOME/Trash`. Files on other
+//! volumes stay on their own volume, in `$topdir/.Trash/$uid` when the
+//! administrator has set up a shared sticky `.Trash`, or `$topdir/.Trash-$uid`
+//! otherwise, so trashing from a removable drive never copies data home.
+//! Other platforms have no volume trashes, so everything goes to the home
+//! trash there.
+
+use crate::actions::{home_dir, move_path, remove_tree, FileActionError};
+use chrono::NaiveDateTime;
+use std::fs::{self, DirBuilder, File};
+use std::io::{self, Write};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// The home trash, `$XDG_DATA_HOME/Trash` (`~/.local/share/Trash` by default).
+pub fn home_trash() -> PathBuf {
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if Path::new(&dir).is_absolute() => PathBuf::from(dir).join("Trash"),
+        _ => home_dir().join(".local/share/Trash"),
+    }
+}
+
+/// Moves `path` into the trash for its volume and returns its new location
+/// under `files/`.
+///
+/// The `.trashinfo` file is created first, exclusively, so the name is
+/// reserved before anything moves; it is removed again if the move fails.
+pub fn trash(path: &Path) -> Result<PathBuf, FileActionError> {
+    let metadata = fs::symlink_metadata(path)?;
+    let absolute = absolute_path(path)?;
+    let name = absolute.file_name().unwrap_or_default().to_os_string();
+
+    let (trash_dir, info_path) = trash_dir_for(&absolute, device(&metadata))?;
+    let (trashed, info) = reserve_name(&trash_dir, &name.to_string_lossy())?;
+
+    let result = write_info(info, &info_path)
+        .and_then(|_| move_path(&absolute, &trashed))
+        .map_err(FileActionError::from);
+    if let Err(error) = result {
+        let _ = fs::remove_file(info_file(&trashed));
+        return Err(error);
+    }
+
+    if metadata.is_dir() {
+        update_directory_sizes(&trash_dir);
+    }
+
+    Ok(trashed)
+}
+
+/// The trash directory [`trash`] would use for `path`, without creating
+/// anything. `None` when the file's volume has no usable trash.
+pub fn planned_trash_dir(path: &Path) -> Option<PathBuf> {
+    let dev = device(&fs::symlink_metadata(path).ok()?);
+    let absolute = absolute_path(path).ok()?;
+
+    let home = home_trash();
+    let home_dev = device(&home.ancestors().find_map(|dir| fs::metadata(dir).ok())?);
+    if home_dev == dev {
+        return Some(home);
+    }
+
+    let topdir = mount_top(&absolute, dev);
+    let uid = user_id();
+    let shared = topdir.join(".Trash");
+    if fs::symlink_metadata(&shared).is_ok_and(|m| m.is_dir() && is_sticky(&m)) {
+        return Some(shared.join(uid.to_string()));
+    }
+
+    let own = topdir.join(format!(".Trash-{}", uid));
+    let usable = match fs::symlink_metadata(&own) {
+        Ok(metadata) => metadata.is_dir() && owner(&metadata) == uid,
+        Err(_) => is_writable(&topdir),
+    };
+    usable.then_some(own)
+}
+
+// Resolves the parent only, so a symlink is trashed rather than its target
+fn absolute_path(path: &Path) -> Result<PathBuf, FileActionError> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| FileActionError::Other("Invalid file path".to_string()))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)?,
+        _ => std::env::current_dir()?,
+    };
+    Ok(parent.join(name))
+}
+
+/// Drops the bookkeeping for a file that has left the trash: its
+/// `.trashinfo` and any `directorysizes` line.
+pub fn forget(trashed: &Path) {
+    let _ = fs::remove_file(info_file(trashed));
+    if let Some(trash_dir) = trashed.parent().and_then(Path::parent) {
+        update_directory_sizes(trash_dir);
+    }
+}
+
+/// An item sitting in one of the trash directories.
+#[derive(Clone, Debug)]
+pub struct TrashEntry {
+    /// Where the item is now, under the trash's `files/` directory.
+    pub trashed_path: PathBuf,
+    pub original_path: PathBuf,
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Total size, including everything inside a trashed directory.
+    pub size: u64,
+}
+
+/// The home trash and every per-volume trash of this user that exists.
+pub fn trash_dirs() -> Vec<PathBuf> {
+    let uid = user_id();
+    let mut dirs = vec![home_trash()];
+
+    for top in mount_points() {
+        for dir in [top.join(".Trash").join(uid.to_string()), top.join(format!(".Trash-{}", uid))] {
+            if dir.join("info").is_dir() && !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+
+    dirs.retain(|dir| dir.join("info").is_dir());
+    dirs
+}
+
+/// Everything in the trash, oldest deletion first. Info files whose item
+/// has gone missing are skipped.
+pub fn list_entries() -> Result<Vec<TrashEntry>, FileActionError> {
+    let mut entries = Vec::new();
+
+    for trash_dir in trash_dirs() {
+        for info in fs::read_dir(trash_dir.join("info"))?.flatten() {
+            let info_path = info.path();
+            if info_path.extension() != Some(OsStr::new("trashinfo")) {
+                continue;
+            }
+            let trashed_path = trash_dir.join("files").join(info_path.file_stem().unwrap_or_default());
+            if fs::symlink_metadata(&trashed_path).is_err() {
+                continue;
+            }
+            let Ok(contents) = fs::read_to_string(&info_path) else {
+                continue;
+            };
+            let Some((path, deleted_at)) = parse_info(&contents) else {
+                continue;
+            };
+
+            entries.push(TrashEntry {
+                original_path: volume_top(&trash_dir).join(path),
+                size: tree_size(&trashed_path),
+                trashed_path,
+                deleted_at,
+            });
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.deleted_at);
+    Ok(entries)
+}
+
+/// Moves a trashed item back out of the trash.
+///
+/// With no `target_dir` it returns to its original path; otherwise it is
+/// placed in `target_dir` under its original name. Existing files are never
+/// overwritten. Returns the restored path.
+pub fn restore(entry: &TrashEntry, target_dir: Option<&Path>) -> Result<PathBuf, FileActionError> {
+    let target = match target_dir {
+        Some(dir) => dir.join(entry.original_path.file_name().unwrap_or_default()),
+        None => entry.original_path.clone(),
+    };
+
+    if fs::symlink_metadata(&target).is_ok() {
+        return Err(FileActionError::Other(format!("{} already exists", target.display())));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    move_path(&entry.trashed_path, &target)?;
+    forget(&entry.trashed_path);
+    Ok(target)
+}
+
+/// Permanently deletes a trashed item.
+pub fn purge(entry: &TrashEntry) -> Result<(), FileActionError> {
+    remove_tree(&entry.trashed_path)?;
+    forget(&entry.trashed_path);
+    Ok(())
+}
+
+/// Entries deleted more than `days` days before `now`. An age too large
+/// for a date matches nothing.
+pub fn older_than(entries: &[TrashEntry], days: u64, now: NaiveDateTime) -> Vec<&TrashEntry> {
+    let cutoff = i64::try_from(days).ok()
+        .and_then(chrono::TimeDelta::try_days)
+        .and_then(|age| now.checked_sub_signed(age));
+    let Some(cutoff) = cutoff else {
+        return Vec::new();
+    };
+    entries.iter()
+        .filter(|entry| entry.deleted_at.is_some_and(|deleted| deleted < cutoff))
+        .collect()
+}
+
+/// The oldest entries that have to go for the rest to fit in `max_bytes`.
+/// Entries without a deletion date go last, since their age is unknown.
+pub fn over_size_cap(entries: &[TrashEntry], max_bytes: u64) -> Vec<&TrashEntry> {
+    let mut sorted: Vec<&TrashEntry> = entries.iter().collect();
+    sorted.sort_by_key(|entry| (entry.deleted_at.is_none(), entry.deleted_at));
+
+    let mut total: u64 = entries.iter().map(|entry| entry.size).sum();
+    sorted.into_iter()
+        .take_while(|entry| {
+            let over = total > max_bytes;
+            total -= entry.size;
+            over
+        })
+        .collect()
+}
+
+// Returns the decoded Path= value and the DeletionDate of a .trashinfo
+fn parse_info(contents: &str) -> Option<(PathBuf, Option<NaiveDateTime>)> {
+    let mut lines = contents.lines().map(str::trim);
+    if lines.next() != Some("[Trash Info]") {
+        return None;
+    }
+
+    let mut path = None;
+    let mut deleted_at = None;
+    for line in lines {
+        if line.starts_with('[') {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Path=") {
+            path = Some(decode_path(value));
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            deleted_at = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S").ok();
+        }
+    }
+    path.map(|path| (path, deleted_at))
+}
+
+// Info paths in a volume trash are relative to the volume's top directory:
+// the parent of .Trash-$uid, or the grandparent of .Trash/$uid
+fn volume_top(trash_dir: &Path) -> PathBuf {
+    if trash_dir == home_trash() {
+        return PathBuf::from("/");
+    }
+    let is_own = trash_dir.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with(".Trash-"));
+    let levels = if is_own { 1 } else { 2 };
+    trash_dir.ancestors().nth(levels).unwrap_or(Path::new("/")).to_path_buf()
+}
+
+// Mount points from /proc/self/mounts, where spaces are escaped as \040
+fn mount_points() -> Vec<PathBuf> {
+    let Ok(mounts) = fs::read_to_string("/proc/self/mounts") else {
+        return Vec::new();
+    };
+
+    mounts.lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        .map(|field| {
+            PathBuf::from(field
+                .replace("\\040", " ")
+                .replace("\\011", "\t")
+                .replace("\\012", "\n")
+                .replace("\\134", "\\"))
+        })
+        .collect()
+}
+
+/// Total size of the regular files at or under `path`.
+pub(crate) fn tree_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+// Picks the trash directory for a file on device `dev`, along with the
+// path to record in its .trashinfo
+fn trash_dir_for(absolute: &Path, dev: u64) -> Result<(PathBuf, PathBuf), FileActionError> {
+    let home = home_trash();
+    create_trash_dir(&home)?;
+    if device(&fs::metadata(&home)?) == dev {
+        return Ok((home, absolute.to_path_buf()));
+    }
+
+    let topdir = mount_top(absolute, dev);
+    let trash_dir = topdir_trash(&topdir).ok_or_else(|| {
+        FileActionError::Other(format!("No usable trash directory on the volume at {}", topdir.display()))
+    })?;
+
+    // Paths in a volume's own trash are relative to the top of the volume
+    let relative = absolute.strip_prefix(&topdir).unwrap_or(absolute);
+    Ok((trash_dir, relative.to_path_buf()))
+}
+
+// Walks up while the parent directory is still on the same device
+fn mount_top(absolute: &Path, dev: u64) -> PathBuf {
+    let mut top = absolute.parent().unwrap_or(absolute).to_path_buf();
+    while let Some(parent) = top.parent() {
+        match fs::metadata(parent) {
+            Ok(metadata) if device(&metadata) == dev => top = parent.to_path_buf(),
+            _ => break,
+        }
+    }
+    top
+}
+
+fn topdir_trash(topdir: &Path) -> Option<PathBuf> {
+    let uid = user_id();
+
+    // A shared .Trash must be a real directory with the sticky bit set,
+    // otherwise other users could tamper with it
+    let shared = topdir.join(".Trash");
+    if let Ok(metadata) = fs::symlink_metadata(&shared) {
+        if metadata.is_dir() && is_sticky(&metadata) {
+            let dir = shared.join(uid.to_string());
+            if create_trash_dir(&dir).is_ok() {
+                return Some(dir);
+            }
+        }
+    }
+
+    let own = topdir.join(format!(".Trash-{}", uid));
+    create_trash_dir(&own).ok().map(|_| own)
+}
+
+fn create_trash_dir(dir: &Path) -> Result<(), FileActionError> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir.join("files"))?;
+    builder.create(dir.join("info"))?;
+
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || owner(&metadata) != user_id() {
+        return Err(FileActionError::Other(format!("{} is not a trash directory we own", dir.display())));
+    }
+    Ok(())
+}
+
+// Claims a free name by creating its .trashinfo with O_EXCL
+fn reserve_name(trash_dir: &Path, name: &str) -> Result<(PathBuf, File), FileActionError> {
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot..]),
+        _ => (name, ""),
+    };
+
+    for counter in 1.. {
+        let candidate = if counter == 1 {
+            name.to_string()
+        } else {
+            format!("{}.{}{}", stem, counter, extension)
+        };
+        let trashed = trash_dir.join("files").join(&candidate);
+        if fs::symlink_metadata(&trashed).is_ok() {
+            continue;
+        }
+
+        match File::options().write(true).create_new(true).open(info_file(&trashed)) {
+            Ok(info) => return Ok((trashed, info)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error.into()),
+        }
+    }
+    unreachable!()
+}
+
+fn write_info(mut info: File, original: &Path) -> io::Result<()> {
+    let deletion_date = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S");
+    write!(info, "[Trash Info]\nPath={}\nDeletionDate={}\n", encode_path(original), deletion_date)?;
+    info.sync_all()
+}
+
+// Trash/files/<name> has its info at Trash/info/<name>.trashinfo
+pub(crate) fn info_file(trashed: &Path) -> PathBuf {
+    let name = trashed.file_name().unwrap_or_default().to_string_lossy();
+    let trash_dir = trashed.parent().and_then(Path::parent).unwrap_or(Path::new(""));
+    trash_dir.join("info").join(format!("{}.trashinfo", name))
+}
+
+/// Percent-encodes a path for a `.trashinfo` `Path=` line. Unreserved URI
+/// characters and `/` are kept as they are.
+pub fn encode_path(path: &Path) -> String {
+    let bytes = path_bytes(path);
+    let mut encoded = String::with_capacity(bytes.len());
+    for &byte in bytes.iter() {
+        if byte.is_ascii_alphanumeric() || b"-_.~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Reverses [`encode_path`]; malformed escapes are kept literally.
+pub fn decode_path(encoded: &str) -> PathBuf {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| encoded.get(i + 1..i + 3))
+            .flatten()
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    path_from_bytes(decoded)
+}
+
+// Rewrites Trash/directorysizes so it lists every trashed directory, as
+// "size mtime name" with the mtime of the directory's .trashinfo. Written
+// to a temporary file and renamed in, as the spec requires.
+fn update_directory_sizes(trash_dir: &Path) {
+    let Ok(entries) = fs::read_dir(trash_dir.join("files")) else {
+        return;
+    };
+
+    let mut contents = String::new();
+    for entry in entries.flatten() {
+        if !entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            continue;
+        }
+        let Ok(info) = fs::metadata(info_file(&entry.path())) else {
+            continue;
+        };
+
+        let mtime = info.modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs());
+        let name = entry.file_name();
+        contents.push_str(&format!("{} {} {}\n", tree_size(&entry.path()), mtime, encode_path(Path::new(&name))));
+    }
+
+    let path = trash_dir.join("directorysizes");
+    let temp_path = trash_dir.join(format!("directorysizes.{}", std::process::id()));
+    if fs::write(&temp_path, contents).and_then(|_| fs::rename(&temp_path, &path)).is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+}
+
+// Unix keeps per-volume trashes and raw byte paths; elsewhere every file
+// counts as being on the home volume
+
+#[cfg(unix)]
+fn device(metadata: &fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::dev(metadata)
+}
+
+#[cfg(not(unix))]
+fn device(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+#[cfg(unix)]
+fn user_id() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+#[cfg(not(unix))]
+fn user_id() -> u32 {
+    0
+}
+
+#[cfg(unix)]
+fn owner(metadata: &fs::Metadata) -> u32 {
+    std::os::unix::fs::MetadataExt::uid(metadata)
+}
+
+#[cfg(not(unix))]
+fn owner(_metadata: &fs::Metadata) -> u32 {
+    user_id()
+}
+
+#[cfg(unix)]
+fn is_sticky(metadata: &fs::Metadata) -> bool {
+    std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o1000 != 0
+}
+
+#[cfg(not(unix))]
+fn is_sticky(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn is_writable(dir: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    std::ffi::CString::new(dir.as_os_str().as_bytes())
+        .is_ok_and(|c_path| unsafe { libc::access(c_path.as_ptr(), libc::W_OK) } == 0)
+}
+
+#[cfg(not(unix))]
+fn is_writable(dir: &Path) -> bool {
+    fs::metadata(dir).is_ok_and(|metadata| !metadata.permissions().readonly())
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().into()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    path.to_string_lossy().into_owned().into_bytes().into()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_encoding() {
+        let path = Path::new("/home/me/My Files/100% done ü.txt");
+        let encoded = encode_path(path);
+        assert_eq!(encoded, "/home/me/My%20Files/100%25%20done%20%C3%BC.txt");
+        assert_eq!(decode_path(&encoded), path);
+        assert_eq!(decode_path("bad%zz%4%+1"), Path::new("bad%zz%4%+1"));
+    }
+
+    #[test]
+    fn test_info_file() {
+        assert_eq!(
+            info_file(Path::new("/t/Trash/files/a.txt")),
+            PathBuf::from("/t/Trash/info/a.txt.trashinfo")
+        );
+        assert_eq!(volume_top(Path::new("/media/usb/.Trash-1000")), PathBuf::from("/media/usb"));
+        assert_eq!(volume_top(Path::new("/media/usb/.Trash/1000")), PathBuf::from("/media/usb"));
+    }
+
+    #[test]
+    fn test_purge_selection() {
+        let info = "[Trash Info]\nPath=docs/a%20b.txt\nDeletionDate=2024-03-01T10:00:00\n";
+        let (path, deleted_at) = parse_info(info).unwrap();
+        assert_eq!(path, PathBuf::from("docs/a b.txt"));
+
+        let entry = |day: u32, size: u64| TrashEntry {
+            trashed_path: PathBuf::new(),
+            original_path: PathBuf::from(day.to_string()),
+            deleted_at: chrono::NaiveDate::from_ymd_opt(2024, 3, day).and_then(|d| d.and_hms_opt(10, 0, 0)),
+            size,
+        };
+        let entries = vec![entry(1, 50), entry(5, 30), entry(9, 20)];
+        assert_eq!(entries[0].deleted_at, deleted_at);
+
+        let now = entries[2].deleted_at.unwrap();
+        assert_eq!(older_than(&entries, 3, now).len(), 2);
+        assert!(older_than(&entries, u64::MAX, now).is_empty());
+        assert!(older_than(&entries, 100_000_000_000_000, now).is_empty());
+        assert_eq!(over_size_cap(&entries, 60).len(), 1);
+        assert_eq!(over_size_cap(&entries, 10).len(), 3);
+        assert!(over_size_cap(&entries, 100).is_empty());
+
+        // An undated entry is kept while dated ones can make room
+        let entries = vec![entry(0, 40), entry(1, 50), entry(5, 30)];
+        let doomed = over_size_cap(&entries, 50);
+        assert_eq!(doomed.len(), 2);
+        assert!(doomed.iter().all(|entry| entry.deleted_at.is_some()));
+    }
+}
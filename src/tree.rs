@@ -0,0 +1,191 @@
+//! How much each directory of a scan weighs, counting everything under it.
+//!
+//! A [`DirTree`] is built from the files a scan found, so it only has the
+//! directories holding at least one of them; empty directories and files
+//! the scan skipped do not show up or count.
+
+use crate::rules::AgeBasis;
+use crate::scanner::FileInfo;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub struct DirNode {
+    pub path: String,
+    pub parent: Option<usize>,
+    /// Subdirectories, as indexes into [`DirTree::nodes`].
+    pub children: Vec<usize>,
+    /// Files directly inside, as indexes into the files the tree was built from.
+    pub files: Vec<usize>,
+    /// Total size of the files at any depth below.
+    pub size: u64,
+    pub file_count: usize,
+    /// The newest and oldest file timestamps below, by the tree's age basis.
+    pub newest_secs: u64,
+    pub oldest_secs: u64,
+}
+
+impl DirNode {
+    pub fn name(&self) -> String {
+        Path::new(&self.path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.clone())
+    }
+}
+
+/// Directories under a scanned folder with their totals. The folder itself
+/// is node 0, and every node comes after its parent.
+#[derive(Clone, Debug)]
+pub struct DirTree {
+    pub nodes: Vec<DirNode>,
+    /// The age basis the newest and oldest timestamps were taken by.
+    pub basis: AgeBasis,
+    by_path: HashMap<PathBuf, usize>,
+}
+
+impl DirTree {
+    /// Builds the tree for `files` found under `root`, dating each file by
+    /// `basis`. Files outside `root` are counted in the root.
+    pub fn build(root: &str, files: &[FileInfo], basis: AgeBasis) -> Self {
+        let mut tree = Self { nodes: Vec::new(), basis, by_path: HashMap::new() };
+        tree.add_node(PathBuf::from(root), None);
+
+        for (index, file) in files.iter().enumerate() {
+            let parent = Path::new(&file.path)
+                .parent()
+                .filter(|dir| dir.starts_with(root))
+                .map(|dir| tree.node_for(dir, Path::new(root)))
+                .unwrap_or(0);
+            let timestamp = basis.timestamp(file);
+            let node = &mut tree.nodes[parent];
+            node.files.push(index);
+            node.size += file.size;
+            node.file_count += 1;
+            node.newest_secs = node.newest_secs.max(timestamp);
+            node.oldest_secs = node.oldest_secs.min(timestamp);
+        }
+
+        // Children come after their parents, so one pass from the end adds
+        // every subtree into its parent
+        for index in (1..tree.nodes.len()).rev() {
+            let node = &tree.nodes[index];
+            let (parent, size, count, newest, oldest) = (node.parent.unwrap(), node.size, node.file_count, node.newest_secs, node.oldest_secs);
+            let parent = &mut tree.nodes[parent];
+            parent.size += size;
+            parent.file_count += count;
+            parent.newest_secs = parent.newest_secs.max(newest);
+            parent.oldest_secs = parent.oldest_secs.min(oldest);
+        }
+        if tree.nodes[0].file_count == 0 {
+            tree.nodes[0].oldest_secs = 0;
+        }
+        tree
+    }
+
+    pub fn root(&self) -> &DirNode {
+        &self.nodes[0]
+    }
+
+    /// The node for a directory, if any file was found under it.
+    pub fn find(&self, path: &str) -> Option<usize> {
+        self.by_path.get(Path::new(path)).copied()
+    }
+
+    /// A node's subdirectories, largest first.
+    pub fn children_by_size(&self, node: usize) -> Vec<&DirNode> {
+        let mut children: Vec<&DirNode> = self.nodes[node].children.iter().map(|&child| &self.nodes[child]).collect();
+        children.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        children
+    }
+
+    /// The `count` largest directories anywhere below the root that are not
+    /// just their one subdirectory over again.
+    pub fn largest(&self, count: usize) -> Vec<&DirNode> {
+        let mut nodes: Vec<&DirNode> = self.nodes[1..].iter()
+            .filter(|node| !(node.files.is_empty() && node.children.len() == 1))
+            .collect();
+        nodes.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        nodes.truncate(count);
+        nodes
+    }
+
+    // Finds or adds the node for `dir`, adding its parents first
+    fn node_for(&mut self, dir: &Path, root: &Path) -> usize {
+        if let Some(&index) = self.by_path.get(dir) {
+            return index;
+        }
+        let parent = match dir.parent() {
+            Some(parent) if dir != root && parent.starts_with(root) => self.node_for(parent, root),
+            _ => 0,
+        };
+        self.add_node(dir.to_path_buf(), Some(parent))
+    }
+
+    fn add_node(&mut self, path: PathBuf, parent: Option<usize>) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(DirNode {
+            path: path.display().to_string(),
+            parent,
+            children: Vec::new(),
+            files: Vec::new(),
+            size: 0,
+            file_count: 0,
+            newest_secs: 0,
+            oldest_secs: u64::MAX,
+        });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(index);
+        }
+        self.by_path.insert(path, index);
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64, modified: u64) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            size,
+            last_accessed: String::new(),
+            last_access_secs: 0,
+            last_modified: String::new(),
+            last_modified_secs: modified,
+            last_changed_secs: 0,
+            created_secs: None,
+            atime_mode: Default::default(),
+            file_type: "Image".to_string(),
+            is_hidden: false,
+            is_readonly: false,
+            is_executable: false,
+        }
+    }
+
+    #[test]
+    fn test_dir_tree() {
+        let files = [
+            file("/data/a.txt", 1, 100),
+            file("/data/photos/2023/b.jpg", 10, 300),
+            file("/data/photos/2024/c.jpg", 20, 200),
+            file("/data/photos/d.jpg", 5, 50),
+            file("/data/only/deep/e.bin", 100, 400),
+        ];
+        let tree = DirTree::build("/data", &files, AgeBasis::Modify);
+
+        let root = tree.root();
+        assert_eq!((root.size, root.file_count, root.newest_secs, root.oldest_secs), (136, 5, 400, 50));
+        assert_eq!(root.files, [0]);
+
+        let photos = &tree.nodes[tree.find("/data/photos").unwrap()];
+        assert_eq!((photos.size, photos.file_count, photos.newest_secs, photos.oldest_secs), (35, 3, 300, 50));
+        let names: Vec<String> = tree.children_by_size(0).iter().map(|node| node.name()).collect();
+        assert_eq!(names, ["only", "photos"]);
+
+        // "only" holds nothing but "deep", so it is left out
+        let largest: Vec<&str> = tree.largest(3).iter().map(|node| node.path.as_str()).collect();
+        assert_eq!(largest, ["/data/only/deep", "/data/photos", "/data/photos/2024"]);
+    }
+}
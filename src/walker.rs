@@ -0,0 +1,402 @@
+// Parallel directory walk behind `scanner::scan_folder_with_progress`.
+//
+// Each worker keeps its own queue of directories to read, taking the most
+// recently found from the back and, when it runs dry, stealing the oldest
+// from another worker's front. Whole subtrees therefore tend to stay on one
+// thread while idle threads pick up the large directories near the root.
+// Files are sent back to the calling thread, which reports progress; each
+// carries the readdir positions on its path so the single-threaded walk
+// order can be restored on request.
+//
+// Given a scan index, a directory whose mtime has not changed since it was
+// indexed is not read again: its files and subdirectories are taken from
+// the index, and only the subdirectories are visited.
+
+use crate::index::{DirRecord, IndexEntry, ScanIndex};
+use crate::mounts::MountTable;
+use crate::protect::ProtectedPaths;
+use crate::scanner::{file_info_from_metadata, filter_reason, FileInfo, ScanOptions, ScanProgress};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// How many files are scanned between progress reports
+const PROGRESS_INTERVAL: u64 = 500;
+
+// Files a worker gathers before handing them over
+const BATCH_SIZE: usize = 256;
+
+// A directory waiting to be read
+struct Task {
+    dir: PathBuf,
+    depth: usize,
+    key: Vec<u32>,
+    // Which top-level entry it belongs to, for progress
+    top: usize,
+}
+
+enum Event {
+    Files(Vec<(Vec<u32>, FileInfo)>),
+    Dir(String, DirRecord),
+    // Every directory under a top-level entry has been read
+    TopDone(PathBuf),
+}
+
+// What an entry turned out to be, following symlinks if asked to
+enum Kind {
+    Dir,
+    File(fs::Metadata),
+    Skip,
+}
+
+// A directory's entries worth keeping: subdirectories (without a file) and
+// files that pass the scan filters, each with its readdir position
+type Listing = Vec<(u32, PathBuf, Option<FileInfo>)>;
+
+struct Shared<'a> {
+    queues: Vec<Mutex<VecDeque<Task>>>,
+    // Directories queued or being read; the walk is over when it reaches 0
+    pending: AtomicUsize,
+    top_pending: Vec<AtomicUsize>,
+    top_paths: Vec<PathBuf>,
+    options: &'a ScanOptions,
+    protected: ProtectedPaths,
+    cancel: &'a AtomicBool,
+    // Directories already entered through symlinks, to stop at loops
+    visited: Mutex<HashSet<DirIdentity>>,
+    // The index being refreshed, if any, and when it was written
+    previous: Option<(HashMap<String, DirRecord>, i64)>,
+}
+
+pub(crate) fn walk<F>(
+    folder: &str,
+    options: &ScanOptions,
+    cancel: &AtomicBool,
+    mut on_progress: F,
+    mut index: Option<&mut ScanIndex>,
+) -> Vec<FileInfo>
+where
+    F: FnMut(&ScanProgress),
+{
+    let started_at = chrono::Utc::now().timestamp();
+    let root = Path::new(folder);
+    let protected = ProtectedPaths::load();
+    let mut mounts = MountTable::load();
+    let mut found: Vec<(Vec<u32>, FileInfo)> = Vec::new();
+    let mut records = HashMap::new();
+    let mut progress = ScanProgress::default();
+
+    let blocked = !options.include_system && protected.covering(root).is_some();
+    if !blocked && !cancel.load(Ordering::Relaxed) {
+        // A file given as the folder is scanned on its own, as walkdir would
+        if let Some(metadata) = fs::metadata(root).ok().filter(|metadata| metadata.is_file()) {
+            progress.files_scanned = 1;
+            progress.bytes_seen = metadata.len();
+            on_progress(&progress);
+            return match filter_reason(root, options) {
+                None => vec![file_info_from_metadata(root, &metadata, &mut mounts)],
+                Some(_) => Vec::new(),
+            };
+        }
+    }
+
+    let threads = match options.threads {
+        0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        n => n,
+    };
+    let mut shared = Shared {
+        queues: (0..threads).map(|_| Mutex::new(VecDeque::new())).collect(),
+        pending: AtomicUsize::new(0),
+        top_pending: Vec::new(),
+        top_paths: Vec::new(),
+        options,
+        protected,
+        cancel,
+        visited: Mutex::new(HashSet::new()),
+        previous: index.as_mut().map(|index| (std::mem::take(&mut index.dirs), index.scanned_at)),
+    };
+
+    let listing = if blocked || options.max_depth == Some(0) || cancel.load(Ordering::Relaxed) {
+        Vec::new()
+    } else {
+        match shared.list(root, &mut mounts) {
+            Some((listing, record)) => {
+                if let Some(record) = record.filter(|_| index.is_some()) {
+                    records.insert(root.display().to_string(), record);
+                }
+                listing
+            }
+            None => Vec::new(),
+        }
+    };
+    progress.entries_total = listing.len();
+    shared.top_pending = (0..listing.len()).map(|_| AtomicUsize::new(0)).collect();
+    shared.top_paths = listing.iter().map(|(_, path, _)| path.clone()).collect();
+
+    // The top-level entries are handled here, spreading their directories
+    // over the workers' queues
+    for (top, (position, path, file)) in listing.into_iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        progress.current_path = path.display().to_string();
+        match file {
+            None if options.max_depth.is_none_or(|max| max > 1) => {
+                shared.top_pending[top].store(1, Ordering::Relaxed);
+                shared.pending.fetch_add(1, Ordering::Relaxed);
+                let task = Task { dir: path, depth: 1, key: vec![position], top };
+                shared.queues[top % threads].lock().unwrap().push_back(task);
+                continue;
+            }
+            None => {}
+            Some(info) => {
+                progress.files_scanned += 1;
+                progress.bytes_seen += info.size;
+                found.push((vec![position], info));
+            }
+        }
+        progress.entries_done += 1;
+        on_progress(&progress);
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let record = index.is_some();
+    std::thread::scope(|scope| {
+        for worker in 0..threads {
+            let sender = sender.clone();
+            let shared = &shared;
+            let mounts = mounts.clone();
+            scope.spawn(move || shared.work(worker, sender, mounts, record));
+        }
+        drop(sender);
+
+        for event in receiver {
+            match event {
+                Event::Files(files) => {
+                    let before = progress.files_scanned / PROGRESS_INTERVAL;
+                    progress.files_scanned += files.len() as u64;
+                    progress.bytes_seen += files.iter().map(|(_, info)| info.size).sum::<u64>();
+                    found.extend(files);
+                    if progress.files_scanned / PROGRESS_INTERVAL != before {
+                        on_progress(&progress);
+                    }
+                }
+                Event::Dir(path, record) => {
+                    records.insert(path, record);
+                }
+                Event::TopDone(path) => {
+                    progress.entries_done += 1;
+                    progress.current_path = path.display().to_string();
+                    on_progress(&progress);
+                }
+            }
+        }
+    });
+
+    if let Some(index) = index {
+        index.dirs = records;
+        index.scanned_at = started_at;
+    }
+
+    on_progress(&progress);
+    if options.ordered {
+        found.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    }
+    found.into_iter().map(|(_, info)| info).collect()
+}
+
+impl Shared<'_> {
+    fn work(&self, worker: usize, sender: mpsc::Sender<Event>, mut mounts: MountTable, record: bool) {
+        let mut batch = Vec::new();
+        loop {
+            if self.cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            match self.next_task(worker) {
+                Some(task) => self.read(worker, task, &mut batch, &sender, &mut mounts, record),
+                None if self.pending.load(Ordering::Acquire) == 0 => break,
+                // Others are still reading and may queue more
+                None => std::thread::sleep(Duration::from_micros(200)),
+            }
+        }
+        if !batch.is_empty() {
+            let _ = sender.send(Event::Files(batch));
+        }
+    }
+
+    fn next_task(&self, worker: usize) -> Option<Task> {
+        if let Some(task) = self.queues[worker].lock().unwrap().pop_back() {
+            return Some(task);
+        }
+        let count = self.queues.len();
+        (1..count).find_map(|offset| self.queues[(worker + offset) % count].lock().unwrap().pop_front())
+    }
+
+    fn read(
+        &self,
+        worker: usize,
+        task: Task,
+        batch: &mut Vec<(Vec<u32>, FileInfo)>,
+        sender: &mpsc::Sender<Event>,
+        mounts: &mut MountTable,
+        record: bool,
+    ) {
+        let depth = task.depth + 1;
+        let descend = self.options.max_depth.is_none_or(|max| depth < max);
+
+        if let Some((listing, dir_record)) = self.list(&task.dir, mounts) {
+            if let Some(dir_record) = dir_record.filter(|_| record) {
+                let _ = sender.send(Event::Dir(task.dir.display().to_string(), dir_record));
+            }
+            for (position, path, file) in listing {
+                let mut key = task.key.clone();
+                key.push(position);
+                match file {
+                    None if descend => {
+                        self.pending.fetch_add(1, Ordering::AcqRel);
+                        self.top_pending[task.top].fetch_add(1, Ordering::AcqRel);
+                        self.queues[worker].lock().unwrap().push_back(Task { dir: path, depth, key, top: task.top });
+                    }
+                    None => {}
+                    Some(info) => {
+                        batch.push((key, info));
+                        if batch.len() >= BATCH_SIZE {
+                            let _ = sender.send(Event::Files(std::mem::take(batch)));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Children were counted before this directory is let go, so the
+        // totals cannot reach 0 while work remains
+        if self.top_pending[task.top].fetch_sub(1, Ordering::AcqRel) == 1 {
+            if !batch.is_empty() {
+                let _ = sender.send(Event::Files(std::mem::take(batch)));
+            }
+            let _ = sender.send(Event::TopDone(self.top_paths[task.top].clone()));
+        }
+        self.pending.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    // The entries of `dir`, from the index if it has not changed since, and
+    // the record to index it under. `None` if it cannot be read or was
+    // already visited through a symlink.
+    fn list(&self, dir: &Path, mounts: &mut MountTable) -> Option<(Listing, Option<DirRecord>)> {
+        let metadata = fs::metadata(dir).ok()?;
+        if !self.enter(dir, &metadata) {
+            return None;
+        }
+        let mtime = dir_mtime(&metadata);
+
+        // A directory changed in the same second the index was written may
+        // have changed again after it was read, so it is read again
+        let indexed = self.previous.as_ref().and_then(|(dirs, scanned_at)| {
+            dirs.get(&dir.display().to_string()).filter(|record| record.mtime == mtime && mtime.0 < *scanned_at)
+        });
+        if let Some(record) = indexed {
+            let listing = record.entries.iter()
+                .map(|entry| (entry.position, dir.join(&entry.name), entry.file.clone()))
+                .collect();
+            return Some((listing, Some(record.clone())));
+        }
+
+        let mut listing = Vec::new();
+        let mut readable_names = true;
+        for (position, entry) in fs::read_dir(dir).ok()?.flatten().enumerate() {
+            let path = entry.path();
+            if !self.options.include_system && self.protected.covering(&path).is_some() {
+                continue;
+            }
+            let position = position as u32;
+            match self.classify(&entry, &path) {
+                Kind::Dir => listing.push((position, path, None)),
+                Kind::File(metadata) if filter_reason(&path, self.options).is_none() => {
+                    let info = file_info_from_metadata(&path, &metadata, mounts);
+                    listing.push((position, path, Some(info)));
+                }
+                _ => continue,
+            }
+            readable_names &= entry.file_name().to_str().is_some();
+        }
+
+        // Names that are not UTF-8 could not be joined back into paths, so
+        // such directories are always read
+        let record = readable_names.then(|| DirRecord {
+            mtime,
+            entries: listing.iter()
+                .map(|(position, path, file)| IndexEntry {
+                    position: *position,
+                    name: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                    file: file.clone(),
+                })
+                .collect(),
+        });
+        Some((listing, record))
+    }
+
+    // Symlinks are only followed when the options say so, as in a walkdir
+    // walk; otherwise they are neither files nor directories
+    fn classify(&self, entry: &fs::DirEntry, path: &Path) -> Kind {
+        let Ok(file_type) = entry.file_type() else {
+            return Kind::Skip;
+        };
+        let metadata = if file_type.is_symlink() {
+            if !self.options.follow_symlinks {
+                return Kind::Skip;
+            }
+            fs::metadata(path)
+        } else {
+            entry.metadata()
+        };
+        match metadata {
+            Ok(metadata) if metadata.is_dir() => Kind::Dir,
+            Ok(metadata) if metadata.is_file() => Kind::File(metadata),
+            _ => Kind::Skip,
+        }
+    }
+
+    // Whether a directory should be read, which is always unless following
+    // symlinks has led back to one already seen
+    fn enter(&self, dir: &Path, metadata: &fs::Metadata) -> bool {
+        !self.options.follow_symlinks || self.visited.lock().unwrap().insert(dir_identity(dir, metadata))
+    }
+}
+
+// What makes two paths the same directory: the device and inode on Unix,
+// the resolved path elsewhere
+#[cfg(unix)]
+type DirIdentity = (u64, u64);
+
+#[cfg(not(unix))]
+type DirIdentity = PathBuf;
+
+#[cfg(unix)]
+fn dir_identity(_dir: &Path, metadata: &fs::Metadata) -> DirIdentity {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn dir_identity(dir: &Path, _metadata: &fs::Metadata) -> DirIdentity {
+    fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+}
+
+// Seconds and nanoseconds, as the index records them
+#[cfg(unix)]
+fn dir_mtime(metadata: &fs::Metadata) -> (i64, i64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.mtime(), metadata.mtime_nsec())
+}
+
+#[cfg(not(unix))]
+fn dir_mtime(metadata: &fs::Metadata) -> (i64, i64) {
+    metadata.modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or((0, 0), |duration| (duration.as_secs() as i64, duration.subsec_nanos() as i64))
+}
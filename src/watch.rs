@@ -0,0 +1,395 @@
+//! Keeps a scan up to date from inotify events instead of scanning again.
+//!
+//! A [`Watcher`] watches every directory a scan of its folder would read and
+//! turns what happens in them into [`Change`]s, which [`apply_changes`]
+//! merges into the scanned files. Each directory takes one inotify watch, so
+//! very large trees can run into `fs.inotify.max_user_watches`; the watcher
+//! then gives up with [`WatchError::LimitReached`] and the caller has to fall
+//! back to scanning on a timer. Without inotify, on systems other than
+//! Linux, [`Watcher::new`] always fails with [`WatchError::Unsupported`].
+
+use crate::scanner::{FileInfo, ScanOptions};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use crate::mounts::MountTable;
+#[cfg(target_os = "linux")]
+use crate::protect::ProtectedPaths;
+#[cfg(target_os = "linux")]
+use crate::scanner::{file_info_from_metadata, filter_reason};
+#[cfg(target_os = "linux")]
+use std::collections::HashSet;
+#[cfg(target_os = "linux")]
+use std::ffi::CString;
+#[cfg(target_os = "linux")]
+use std::fs::{self, File};
+#[cfg(target_os = "linux")]
+use std::io::Read;
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, FromRawFd};
+#[cfg(target_os = "linux")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+
+#[cfg(target_os = "linux")]
+const WATCH_MASK: u32 = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MODIFY | libc::IN_CLOSE_WRITE
+    | libc::IN_ATTRIB | libc::IN_MOVED_FROM | libc::IN_MOVED_TO | libc::IN_DELETE_SELF | libc::IN_MOVE_SELF;
+
+// Size of struct inotify_event before the name
+#[cfg(target_os = "linux")]
+const EVENT_HEADER: usize = 16;
+
+// How long to let a burst of events (a file being written, say) gather
+// before reading them, so each file is looked at once
+#[cfg(target_os = "linux")]
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// What happened to the scanned files.
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// A file was created or changed; this is how it is now.
+    Updated(FileInfo),
+    /// A file was removed, or no longer passes the scan's filters.
+    Removed(String),
+    /// A directory was removed or moved away, with everything under it.
+    RemovedDir(String),
+    /// Events were lost, or the folder itself went away; only a new scan
+    /// can catch up.
+    Rescan,
+}
+
+#[derive(Debug)]
+pub enum WatchError {
+    /// The system ran out of inotify watches or instances.
+    LimitReached,
+    /// There is no inotify on this system.
+    Unsupported,
+    Io(io::Error),
+}
+
+impl std::fmt::Display for WatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchError::LimitReached => write!(f, "Too many folders to watch (raise fs.inotify.max_user_watches)"),
+            WatchError::Unsupported => write!(f, "Watching for changes is not supported on this system"),
+            WatchError::Io(error) => write!(f, "Cannot watch for changes: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for WatchError {}
+
+impl From<io::Error> for WatchError {
+    fn from(error: io::Error) -> Self {
+        #[cfg(target_os = "linux")]
+        if matches!(error.raw_os_error(), Some(libc::ENOSPC) | Some(libc::EMFILE)) {
+            return WatchError::LimitReached;
+        }
+        WatchError::Io(error)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct Watcher {
+    inotify: File,
+    root: PathBuf,
+    options: ScanOptions,
+    protected: ProtectedPaths,
+    mounts: MountTable,
+    watches: HashMap<i32, PathBuf>,
+}
+
+#[cfg(target_os = "linux")]
+impl Watcher {
+    /// Watches `folder` and the directories under it that a scan with
+    /// `options` would read.
+    pub fn new(folder: &str, options: &ScanOptions) -> Result<Self, WatchError> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let mut watcher = Self {
+            inotify: unsafe { File::from_raw_fd(fd) },
+            root: PathBuf::from(folder),
+            options: options.clone(),
+            protected: ProtectedPaths::load(),
+            mounts: MountTable::load(),
+            watches: HashMap::new(),
+        };
+
+        // The scan that comes with the watcher already has the files
+        let root = watcher.root.clone();
+        watcher.add_tree(&root, None)?;
+        Ok(watcher)
+    }
+
+    /// The number of directories being watched.
+    pub fn watched(&self) -> usize {
+        self.watches.len()
+    }
+
+    /// Waits up to `timeout` for something to happen and returns what did,
+    /// which is nothing if the time ran out.
+    pub fn read_changes(&mut self, timeout: Duration) -> Result<Vec<Change>, WatchError> {
+        if !self.readable(timeout)? {
+            return Ok(Vec::new());
+        }
+        std::thread::sleep(SETTLE);
+
+        let mut changes = Vec::new();
+        // Paths created or changed are looked at once all events are in
+        let mut touched = Vec::new();
+        let mut seen = HashSet::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        while self.readable(Duration::ZERO)? {
+            let length = self.inotify.read(&mut buffer)?;
+            let mut offset = 0;
+            while offset + EVENT_HEADER <= length {
+                let field = |at: usize| u32::from_ne_bytes(buffer[offset + at..offset + at + 4].try_into().unwrap());
+                let (wd, mask, name_length) = (field(0) as i32, field(4), field(12) as usize);
+                let name = &buffer[offset + EVENT_HEADER..offset + EVENT_HEADER + name_length];
+                let name = &name[..name.iter().position(|&byte| byte == 0).unwrap_or(name.len())];
+                offset += EVENT_HEADER + name_length;
+
+                if mask & libc::IN_Q_OVERFLOW != 0 {
+                    changes.push(Change::Rescan);
+                    continue;
+                }
+                let Some(dir) = self.watches.get(&wd).cloned() else {
+                    continue;
+                };
+                if mask & libc::IN_IGNORED != 0 {
+                    self.watches.remove(&wd);
+                    continue;
+                }
+                if name.is_empty() {
+                    // The directory itself; its parent reports it unless it is the root
+                    if mask & (libc::IN_DELETE_SELF | libc::IN_MOVE_SELF) != 0 && dir == self.root {
+                        changes.push(Change::Rescan);
+                    }
+                    continue;
+                }
+
+                let path = dir.join(std::ffi::OsStr::from_bytes(name));
+                if mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) == 0 {
+                    if seen.insert(path.clone()) {
+                        touched.push(path);
+                    }
+                } else if mask & libc::IN_ISDIR != 0 {
+                    self.unwatch_tree(&path);
+                    changes.push(Change::RemovedDir(path.display().to_string()));
+                } else {
+                    changes.push(Change::Removed(path.display().to_string()));
+                }
+            }
+        }
+
+        for path in touched {
+            self.refresh(&path, &mut changes)?;
+        }
+        Ok(changes)
+    }
+
+    fn readable(&self, timeout: Duration) -> io::Result<bool> {
+        let mut poll = libc::pollfd { fd: self.inotify.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        match unsafe { libc::poll(&mut poll, 1, timeout.as_millis() as libc::c_int) } {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => Ok(false),
+            -1 => Err(io::Error::last_os_error()),
+            ready => Ok(ready > 0),
+        }
+    }
+
+    // Looks at a path that was created or changed: a directory is watched
+    // with everything under it, a file is read again
+    fn refresh(&mut self, path: &Path, changes: &mut Vec<Change>) -> Result<(), WatchError> {
+        match self.metadata(path) {
+            Some(metadata) if metadata.is_dir() => self.add_tree(path, Some(changes)),
+            Some(metadata) if metadata.is_file() && self.keeps_file(path) => {
+                changes.push(Change::Updated(file_info_from_metadata(path, &metadata, &mut self.mounts)));
+                Ok(())
+            }
+            _ => {
+                changes.push(Change::Removed(path.display().to_string()));
+                Ok(())
+            }
+        }
+    }
+
+    // Watches `dir` and the directories under it, reporting the files found
+    // in any not watched before to `changes`. Without `changes` only the
+    // directories are looked at.
+    fn add_tree(&mut self, dir: &Path, mut changes: Option<&mut Vec<Change>>) -> Result<(), WatchError> {
+        let depth = self.depth(dir);
+        if self.options.max_depth.is_some_and(|max| depth >= max) || !self.allowed(dir) {
+            return Ok(());
+        }
+        let Ok(c_path) = CString::new(dir.as_os_str().as_bytes()) else {
+            return Ok(());
+        };
+        let wd = unsafe { libc::inotify_add_watch(self.inotify.as_raw_fd(), c_path.as_ptr(), WATCH_MASK | libc::IN_ONLYDIR) };
+        if wd < 0 {
+            return match WatchError::from(io::Error::last_os_error()) {
+                WatchError::LimitReached => Err(WatchError::LimitReached),
+                // Gone already, or not ours to read; a scan skips it too
+                _ => Ok(()),
+            };
+        }
+        // Watching the same directory again, through a symlink or a second
+        // event, gives back its watch
+        if self.watches.insert(wd, dir.to_path_buf()).is_some() {
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            // The entry's type comes with the listing, so plain files cost
+            // nothing more when they are not reported
+            if changes.is_none() && entry.file_type().is_ok_and(|file_type| file_type.is_file()) {
+                continue;
+            }
+            let path = entry.path();
+            let Some(metadata) = self.metadata(&path) else {
+                continue;
+            };
+            if metadata.is_dir() {
+                self.add_tree(&path, changes.as_deref_mut())?;
+            } else if let Some(changes) = changes.as_deref_mut() {
+                if metadata.is_file() && self.keeps_file(&path) {
+                    changes.push(Change::Updated(file_info_from_metadata(&path, &metadata, &mut self.mounts)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn unwatch_tree(&mut self, dir: &Path) {
+        let fd = self.inotify.as_raw_fd();
+        self.watches.retain(|&wd, path| {
+            if !path.starts_with(dir) {
+                return true;
+            }
+            unsafe { libc::inotify_rm_watch(fd, wd) };
+            false
+        });
+    }
+
+    // Symlinks count as what they point to only when the scan follows them
+    fn metadata(&self, path: &Path) -> Option<fs::Metadata> {
+        let metadata = fs::symlink_metadata(path).ok()?;
+        match metadata.file_type().is_symlink() {
+            true if self.options.follow_symlinks => fs::metadata(path).ok(),
+            true => None,
+            false => Some(metadata),
+        }
+    }
+
+    fn keeps_file(&self, path: &Path) -> bool {
+        self.options.max_depth.is_none_or(|max| self.depth(path) <= max)
+            && self.allowed(path)
+            && filter_reason(path, &self.options).is_none()
+    }
+
+    fn allowed(&self, path: &Path) -> bool {
+        self.options.include_system || self.protected.covering(path).is_none()
+    }
+
+    // Counted as walkdir does: the folder is 0 and its entries 1
+    fn depth(&self, path: &Path) -> usize {
+        path.strip_prefix(&self.root).map(|rest| rest.components().count()).unwrap_or(0)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct Watcher;
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    pub fn new(_folder: &str, _options: &ScanOptions) -> Result<Self, WatchError> {
+        Err(WatchError::Unsupported)
+    }
+
+    pub fn watched(&self) -> usize {
+        0
+    }
+
+    pub fn read_changes(&mut self, _timeout: Duration) -> Result<Vec<Change>, WatchError> {
+        Err(WatchError::Unsupported)
+    }
+}
+
+/// Merges `changes` into `files` in the order they happened. New files go
+/// at the end. [`Change::Rescan`] is left to the caller.
+pub fn apply_changes(files: &mut Vec<FileInfo>, changes: Vec<Change>) {
+    let mut slots: Vec<Option<FileInfo>> = std::mem::take(files).into_iter().map(Some).collect();
+    let mut positions: HashMap<String, usize> = slots.iter()
+        .enumerate()
+        .filter_map(|(position, slot)| slot.as_ref().map(|file| (file.path.clone(), position)))
+        .collect();
+
+    for change in changes {
+        match change {
+            Change::Updated(file) => match positions.get(&file.path) {
+                Some(&position) => slots[position] = Some(file),
+                None => {
+                    positions.insert(file.path.clone(), slots.len());
+                    slots.push(Some(file));
+                }
+            },
+            Change::Removed(path) => {
+                if let Some(position) = positions.remove(&path) {
+                    slots[position] = None;
+                }
+            }
+            Change::RemovedDir(dir) => {
+                positions.retain(|path, &mut position| {
+                    let inside = Path::new(path).starts_with(&dir);
+                    if inside {
+                        slots[position] = None;
+                    }
+                    !inside
+                });
+            }
+            Change::Rescan => {}
+        }
+    }
+    *files = slots.into_iter().flatten().collect();
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_watch_changes() {
+        let dir = std::env::temp_dir().join(format!("trashdoctor-watch-{}", std::process::id()));
+        fs::create_dir_all(dir.join("old")).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("old/b.txt"), "b").unwrap();
+        let folder = dir.to_str().unwrap();
+        let mut files = crate::scanner::scan_folder_with_options(folder, &ScanOptions::default());
+        let mut watcher = Watcher::new(folder, &ScanOptions::default()).unwrap();
+        assert_eq!(watcher.watched(), 2);
+
+        fs::write(dir.join("a.txt"), "longer").unwrap();
+        fs::write(dir.join(".hidden"), "h").unwrap();
+        fs::create_dir_all(dir.join("new/deeper")).unwrap();
+        fs::write(dir.join("new/deeper/c.txt"), "c").unwrap();
+        fs::remove_dir_all(dir.join("old")).unwrap();
+
+        // Events may arrive over more than one read
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline && !files.iter().any(|f| f.path.ends_with("c.txt")) {
+            apply_changes(&mut files, watcher.read_changes(Duration::from_millis(200)).unwrap());
+        }
+        apply_changes(&mut files, watcher.read_changes(Duration::from_millis(200)).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut paths: Vec<&str> = files.iter().map(|f| f.path.strip_prefix(folder).unwrap()).collect();
+        paths.sort();
+        assert_eq!(paths, ["/a.txt", "/new/deeper/c.txt"]);
+        assert_eq!(files.iter().find(|f| f.path.ends_with("a.txt")).unwrap().size, 6);
+    }
+}